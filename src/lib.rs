@@ -0,0 +1,29 @@
+//! A read-only reader for SQLite database files.
+//!
+//! ```no_run
+//! use codecrafters_sqlite::Connection;
+//!
+//! let connection = Connection::open("sample.db")?;
+//! let mut statement = connection.prepare("SELECT name FROM apples")?;
+//! for row in statement.query()? {
+//!     println!("{}", row?.values()[0]);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod modules;
+
+pub use modules::{
+    ast::{AggregateCall, AggregateFunction, BinaryOp, CompareOp, Expr, FunctionCall, Literal, OrderingTerm, SortOrder, UnaryOp, format_real},
+    btree::{BTreeCursor, TreeKind},
+    connection::{Connection, Row, Rows, Statement},
+    eval::{Collation, Scope},
+    executor::{Aggregate, Distinct, Filter, IndexScan, KeyBound, Limit, NestedLoopJoin, Operator, Project, RowidScan, Sort, SortKey, TableScan},
+    functions::ScalarFunction,
+    header::{DatabaseHeader, TextEncoding},
+    index::Index,
+    pager::Pager,
+    schema::{Schema, SchemaEntry},
+    sorter::DEFAULT_SORT_MEMORY,
+    table::{Affinity, Column, Table},
+};
@@ -1,4 +1,18 @@
 pub mod table;
+pub mod index;
 pub mod sql_parser;
 pub mod ast;
+pub mod header;
+pub mod connection;
+pub mod btree;
 pub mod helpers;
+pub mod pager;
+pub mod schema;
+pub mod eval;
+pub mod functions;
+pub mod executor;
+pub mod aggregate;
+pub mod sorter;
+pub mod planner;
+#[cfg(test)]
+pub mod testing;
@@ -0,0 +1,321 @@
+use std::{cmp::Ordering, collections::HashSet};
+
+use anyhow::{Result, bail};
+
+use crate::modules::{ast::{AggregateCall, AggregateFunction, Literal}, eval::{Collation, Scope, number, text}};
+
+/// The rows of a group folded so far, along with the input row that gives the bare columns their
+/// values. Like in SQLite, that is the row that last set the result of the last min() or max()
+/// call, else the first row.
+pub struct Group {
+    row: Option<Vec<Literal>>,
+    accumulators: Vec<Accumulator>,
+    /// Position of the min() or max() call that picks the row
+    extreme: Option<usize>,
+}
+
+impl Group {
+    pub fn new(calls: &[AggregateCall], scope: &Scope) -> Self {
+        let extreme = calls.iter().rposition(|call| matches!(call.function, AggregateFunction::Min | AggregateFunction::Max));
+        Self { row: None, accumulators: calls.iter().map(|call| Accumulator::new(call, scope)).collect(), extreme }
+    }
+
+    pub fn add(&mut self, row: Vec<Literal>, calls: &[AggregateCall], scope: &Scope) -> Result<()> {
+        let mut sets_extreme = false;
+        for (i, (call, accumulator)) in calls.iter().zip(&mut self.accumulators).enumerate() {
+            let args = call.args.iter().map(|arg| scope.eval(arg, &row)).collect::<Result<_>>()?;
+            sets_extreme |= accumulator.step(args, scope)? && self.extreme == Some(i);
+        }
+        if sets_extreme || self.row.is_none() {
+            self.row = Some(row);
+        }
+        Ok(())
+    }
+
+    /// The input row, all NULL when there was none, followed by the aggregate values.
+    pub fn finish(self, scope: &Scope) -> Result<Vec<Literal>> {
+        let mut values = self.row.unwrap_or_else(|| vec![Literal::Null; scope.width()]);
+        for accumulator in &self.accumulators {
+            values.push(accumulator.finish()?);
+        }
+        Ok(values)
+    }
+}
+
+/// Running state of one aggregate call over the rows of a group, following the NULL and type rules
+/// of SQLite's built-in aggregates (https://www.sqlite.org/lang_aggfunc.html).
+struct Accumulator {
+    function: AggregateFunction,
+    /// Text comparisons of min() and max(), and what DISTINCT counts as a duplicate
+    collation: Collation,
+    /// Argument values seen so far, for DISTINCT
+    seen: Option<HashSet<HashKey>>,
+    state: State,
+}
+
+enum State {
+    Count(i64),
+    Sum(Sum),
+    /// Smallest or largest value so far
+    Extreme(Option<Literal>),
+    GroupConcat(Option<String>),
+}
+
+impl Accumulator {
+    fn new(call: &AggregateCall, scope: &Scope) -> Self {
+        let collation = call.args.first().and_then(|arg| scope.collation(arg)).unwrap_or(Collation::Binary);
+        let state = match call.function {
+            AggregateFunction::Count => State::Count(0),
+            AggregateFunction::Sum | AggregateFunction::Avg | AggregateFunction::Total => State::Sum(Sum::default()),
+            AggregateFunction::Min | AggregateFunction::Max => State::Extreme(None),
+            AggregateFunction::GroupConcat => State::GroupConcat(None),
+        };
+        Self { function: call.function, collation, seen: call.distinct.then(HashSet::new), state }
+    }
+
+    /// Adds a row given the values of the arguments. Returns whether the row holds the new minimum
+    /// or maximum, as the values of bare columns then come from it.
+    fn step(&mut self, args: Vec<Literal>, scope: &Scope) -> Result<bool> {
+        let mut args = args.into_iter();
+        let Some(value) = args.next() else {
+            if let State::Count(rows) = &mut self.state {
+                *rows += 1; // count(*)
+            }
+            return Ok(false);
+        };
+        if matches!(value, Literal::Null) {
+            return Ok(false);
+        }
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(HashKey::new(&value, self.collation)) {
+                return Ok(false);
+            }
+        }
+        match &mut self.state {
+            State::Count(rows) => *rows += 1,
+            State::Sum(sum) => sum.add(&value),
+            State::Extreme(extreme) => {
+                let wanted = if self.function == AggregateFunction::Min { Ordering::Less } else { Ordering::Greater };
+                let replace = extreme.as_ref().map_or(true, |extreme| scope.order(&value, extreme, self.collation) == Some(wanted));
+                if replace {
+                    *extreme = Some(value);
+                }
+                return Ok(replace);
+            },
+            State::GroupConcat(joined) => {
+                let value = text(&value);
+                match joined {
+                    Some(joined) => {
+                        // each row's separator goes in front of its value, NULL meaning none
+                        match args.next() {
+                            Some(Literal::Null) => {},
+                            Some(separator) => joined.push_str(&text(&separator)),
+                            None => joined.push(','),
+                        }
+                        joined.push_str(&value);
+                    },
+                    None => *joined = Some(value),
+                }
+            },
+        }
+        Ok(false)
+    }
+
+    fn finish(&self) -> Result<Literal> {
+        Ok(match &self.state {
+            State::Count(rows) => Literal::IntegerLiteral(*rows),
+            State::Sum(sum) => match self.function {
+                AggregateFunction::Sum => sum.sum()?,
+                AggregateFunction::Avg if sum.count == 0 => Literal::Null,
+                AggregateFunction::Avg => Literal::RealLiteral(sum.real() / sum.count as f64),
+                _ => Literal::RealLiteral(sum.real()),
+            },
+            State::Extreme(extreme) => extreme.clone().unwrap_or(Literal::Null),
+            State::GroupConcat(text) => text.clone().map_or(Literal::Null, Literal::StringLiteral),
+        })
+    }
+}
+
+/// Sum of integers, exact until it overflows or meets a real, then compensated like SQLite's
+/// Kahan-Babuska-Neumaier summation.
+#[derive(Default)]
+struct Sum {
+    /// Non-NULL values added
+    count: u64,
+    integer: i64,
+    /// Whether the sum went over to reals
+    approximate: bool,
+    /// Whether the integer sum overflowed with only integers added since
+    overflow: bool,
+    real: f64,
+    error: f64,
+}
+
+/// Integers this large lose precision as f64, so they are added in two parts.
+const EXACT_REAL_LIMIT: i64 = 1 << 52;
+
+impl Sum {
+    fn add(&mut self, value: &Literal) {
+        self.count += 1;
+        match (numeric(value), self.approximate) {
+            (Number::Integer(n), false) => match self.integer.checked_add(n) {
+                Some(sum) => self.integer = sum,
+                None => {
+                    self.overflow = true;
+                    self.start_real();
+                    self.add_integer(n);
+                },
+            },
+            (Number::Real(n), false) => {
+                self.start_real();
+                self.add_real(n);
+            },
+            (Number::Integer(n), true) => self.add_integer(n),
+            (Number::Real(n), true) => {
+                self.overflow = false;
+                self.add_real(n);
+            },
+        }
+    }
+
+    fn start_real(&mut self) {
+        self.approximate = true;
+        let n = self.integer;
+        if n <= -EXACT_REAL_LIMIT || n >= EXACT_REAL_LIMIT {
+            let small = n % 16384;
+            (self.real, self.error) = ((n - small) as f64, small as f64);
+        } else {
+            (self.real, self.error) = (n as f64, 0.0);
+        }
+    }
+
+    fn add_integer(&mut self, n: i64) {
+        if n <= -EXACT_REAL_LIMIT || n >= EXACT_REAL_LIMIT {
+            let small = n % 16384;
+            self.add_real((n - small) as f64);
+            self.add_real(small as f64);
+        } else {
+            self.add_real(n as f64);
+        }
+    }
+
+    fn add_real(&mut self, n: f64) {
+        let sum = self.real + n;
+        if self.real.abs() > n.abs() {
+            self.error += (self.real - sum) + n;
+        } else {
+            self.error += (n - sum) + self.real;
+        }
+        self.real = sum;
+    }
+
+    fn real(&self) -> f64 {
+        if !self.approximate {
+            return self.integer as f64;
+        }
+        if self.error.is_finite() { self.real + self.error } else { self.real }
+    }
+
+    /// Result of sum(): NULL without values, an integer unless a real was added.
+    fn sum(&self) -> Result<Literal> {
+        if self.count == 0 {
+            return Ok(Literal::Null);
+        }
+        if self.overflow {
+            bail!("integer overflow")
+        }
+        Ok(if self.approximate { Literal::RealLiteral(self.real()) } else { Literal::IntegerLiteral(self.integer) })
+    }
+}
+
+enum Number {
+    Integer(i64),
+    Real(f64),
+}
+
+/// The number a value adds to a sum: text that reads as an integer is one, anything else counts
+/// as the real its leading digits spell.
+fn numeric(value: &Literal) -> Number {
+    let whole_integer = match value {
+        Literal::StringLiteral(s) | Literal::Ident(s) => s.trim().parse::<i64>().is_ok(),
+        Literal::BlobLiteral(_) => false,
+        _ => true,
+    };
+    match number(value) {
+        Literal::IntegerLiteral(n) if whole_integer => Number::Integer(n),
+        Literal::IntegerLiteral(n) => Number::Real(n as f64),
+        Literal::RealLiteral(n) => Number::Real(n),
+        _ => Number::Integer(0),
+    }
+}
+
+/// A value reduced so that values equal under a collation are equal keys.
+#[derive(PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Null,
+    Integer(i64),
+    /// Bits of a real with a fraction or out of the integer range
+    Real(u64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl HashKey {
+    pub fn new(value: &Literal, collation: Collation) -> Self {
+        match value {
+            Literal::IntegerLiteral(n) => Self::Integer(*n),
+            Literal::RealLiteral(n) if n.fract() == 0.0 && n.abs() < 9.2e18 => Self::Integer(*n as i64),
+            Literal::RealLiteral(n) => Self::Real(n.to_bits()),
+            Literal::StringLiteral(s) | Literal::Ident(s) => Self::Text(match collation {
+                Collation::Binary => s.clone(),
+                Collation::NoCase => s.to_ascii_lowercase(),
+                Collation::RTrim => s.trim_end_matches(' ').to_string(),
+            }),
+            Literal::BlobLiteral(b) => Self::Blob(b.clone()),
+            Literal::Null => Self::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::testing;
+
+    #[test]
+    fn counts_skip_nulls() {
+        let connection = testing::open("query");
+        let sql = "SELECT count(*), count(c), count(d), count(DISTINCT a), count(DISTINCT b) FROM items";
+        // b is NOCASE, so 'apple' and 'APPLE' are the same value
+        assert_eq!(testing::query(&connection, sql), ["300|240|200|7|3"]);
+    }
+
+    #[test]
+    fn sums_and_extremes() {
+        let connection = testing::open("query");
+        let sql = "SELECT sum(a), sum(c), avg(c), total(d), sum(d), min(c), max(c), min(d), max(d), min(b), max(b) FROM items";
+        assert_eq!(testing::query(&connection, sql), ["903|18000.0|75.0|15150.0|15150.0|0.5|149.5|3|x97|APPLE|cherry"]);
+        let sql = "SELECT typeof(sum(a)), typeof(avg(a)), typeof(total(a)), sum(DISTINCT a) FROM items";
+        assert_eq!(testing::query(&connection, sql), ["integer|real|real|21"]);
+        // over no rows only count() and total() have a value
+        let sql = "SELECT sum(c), avg(c), total(c), min(c), max(c), count(c), group_concat(c) FROM items WHERE c IS NULL";
+        assert_eq!(testing::query(&connection, sql), ["null|null|0.0|null|null|0|null"]);
+    }
+
+    #[test]
+    fn group_concat_joins_values() {
+        let connection = testing::open("query");
+        let sql = "SELECT group_concat(name), group_concat(name, '-'), group_concat(DISTINCT item_id), sum(item_id), avg(item_id) FROM owners";
+        assert_eq!(testing::query(&connection, sql), ["ann,bob,cid,dan,eve|ann-bob-cid-dan-eve|3,10,999|1015|253.75"]);
+    }
+
+    #[test]
+    fn sum_types_and_overflow() {
+        let connection = testing::open("types");
+        assert_eq!(testing::query(&connection, "SELECT total(v) FROM vals WHERE id IN (12, 3)"), ["9.22337203685478e+18"]);
+        assert_eq!(testing::query(&connection, "SELECT count(v), typeof(sum(v)) FROM vals WHERE id IN (17, 18, 19)"), ["3|real"]);
+        assert_eq!(testing::query(&connection, "SELECT sum(v) FROM vals WHERE id IN (17, 2)"), ["0.0"]);
+        let mut statement = connection.prepare("SELECT sum(v) FROM vals WHERE id IN (12, 3)").unwrap();
+        let error = statement.query().unwrap().next().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "integer overflow");
+    }
+}
@@ -1,47 +1,346 @@
-use std::fmt;
-use anyhow::{Result, anyhow};
+use std::{cmp::Ordering, fmt};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Literal(Literal),
-    Equality {
-        column: Literal,
-        condition: Literal,
+    Compare {
+        op: CompareOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
     },
+    /// `left IS right`, equality where NULL equals NULL
+    Is {
+        left: Box<Expr>,
+        right: Box<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    In {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Aggregate(AggregateCall),
+    Function(FunctionCall),
+}
+
+/// Arithmetic, concatenation and bitwise operators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    /// `||`
+    Concat,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    /// `-x`, which SQLite computes as `0 - x`
+    Negate,
+    /// `+x`, which leaves the value as it is
+    Plus,
+    /// `~x`
+    BitNot,
+}
+
+/// A call of an aggregate function, like `count(*)` or `sum(DISTINCT x)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateCall {
+    pub function: AggregateFunction,
+    /// Empty for `count(*)`
+    pub args: Vec<Expr>,
+    pub distinct: bool,
+}
+
+/// A call of a scalar function, looked up by name when the query is planned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+    /// The name as written, for error messages
+    pub name: String,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Total,
+    GroupConcat,
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "avg" => Some(Self::Avg),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "total" => Some(Self::Total),
+            "group_concat" => Some(Self::GroupConcat),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Total => "total",
+            Self::GroupConcat => "group_concat",
+        }
+    }
+
+    /// Numbers of arguments the function accepts.
+    pub fn arity(self) -> std::ops::RangeInclusive<usize> {
+        match self {
+            Self::Count => 0..=1,
+            Self::GroupConcat => 1..=2,
+            _ => 1..=1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Whether two values ordered as `ordering` satisfy the operator.
+    pub fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+        }
+    }
+
+    /// The operator that gives the same result with the operands swapped.
+    pub fn flip(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Le => Self::Ge,
+            Self::Gt => Self::Lt,
+            Self::Ge => Self::Le,
+            op => op,
+        }
+    }
 }
 
 impl Expr {
-    pub fn get_equality(&self) -> Result<(Literal, Literal)> {
-        if let Self::Equality { column, condition } = self {
-            return Ok((column.clone(), condition.clone()))
+    /// The expressions directly inside this one.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Self::Literal(_) => vec![],
+            Self::Compare { left, right, .. } | Self::Is { left, right, .. } | Self::And(left, right) | Self::Or(left, right) => vec![left, right],
+            Self::Between { expr, low, high, .. } => vec![expr, low, high],
+            Self::In { expr, list, .. } => std::iter::once(expr.as_ref()).chain(list).collect(),
+            Self::Not(expr) | Self::Unary { expr, .. } => vec![expr],
+            Self::Binary { left, right, .. } => vec![left, right],
+            Self::Aggregate(call) => call.args.iter().collect(),
+            Self::Function(call) => call.args.iter().collect(),
+        }
+    }
+
+    /// A copy of this expression with the column references `replace` gives an expression for replaced.
+    pub fn replace_columns(&self, replace: &impl Fn(&str) -> Option<Expr>) -> Expr {
+        let boxed = |expr: &Expr| Box::new(expr.replace_columns(replace));
+        match self {
+            Self::Literal(Literal::Ident(name)) => replace(name).unwrap_or_else(|| self.clone()),
+            Self::Literal(_) => self.clone(),
+            Self::Compare { op, left, right } => Self::Compare { op: *op, left: boxed(left), right: boxed(right) },
+            Self::Is { left, right, negated } => Self::Is { left: boxed(left), right: boxed(right), negated: *negated },
+            Self::Between { expr, low, high, negated } => Self::Between { expr: boxed(expr), low: boxed(low), high: boxed(high), negated: *negated },
+            Self::In { expr, list, negated } => Self::In {
+                expr: boxed(expr),
+                list: list.iter().map(|item| item.replace_columns(replace)).collect(),
+                negated: *negated,
+            },
+            Self::And(left, right) => Self::And(boxed(left), boxed(right)),
+            Self::Or(left, right) => Self::Or(boxed(left), boxed(right)),
+            Self::Not(expr) => Self::Not(boxed(expr)),
+            Self::Binary { op, left, right } => Self::Binary { op: *op, left: boxed(left), right: boxed(right) },
+            Self::Unary { op, expr } => Self::Unary { op: *op, expr: boxed(expr) },
+            Self::Aggregate(call) => Self::Aggregate(AggregateCall {
+                args: call.args.iter().map(|arg| arg.replace_columns(replace)).collect(),
+                ..call.clone()
+            }),
+            Self::Function(call) => Self::Function(FunctionCall {
+                name: call.name.clone(),
+                args: call.args.iter().map(|arg| arg.replace_columns(replace)).collect(),
+            }),
+        }
+    }
+
+    /// The aggregate calls in this expression, leaving out any nested in their arguments.
+    pub fn aggregates(&self) -> Vec<&AggregateCall> {
+        match self {
+            Self::Aggregate(call) => vec![call],
+            expr => expr.children().into_iter().flat_map(Expr::aggregates).collect(),
+        }
+    }
+
+    /// The terms of a chain of ANDs, or just this expression when it is not an AND.
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Self::And(left, right) => {
+                let mut terms = left.conjuncts();
+                terms.extend(right.conjuncts());
+                terms
+            },
+            expr => vec![expr],
         }
-        Err(anyhow!("Expr isnt an equality."))
     }
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Literal {
     Ident(String),
     StringLiteral(String),
-    NumberLiteral(f64),
+    IntegerLiteral(i64),
+    RealLiteral(f64),
+    BlobLiteral(Vec<u8>),
     Null
 }
 
 impl Literal {
-    pub fn get_ident(&self) -> Result<String> {
-        if let Self::Ident(s) = self {
-            return Ok(s.clone())
+    /// Position of the variant when comparing literals of different kinds, in the order SQLite
+    /// sorts values: NULL, then numbers, then text, then blobs.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::IntegerLiteral(_) | Self::RealLiteral(_) => 1,
+            Self::StringLiteral(_) => 2,
+            Self::BlobLiteral(_) => 3,
+            Self::Ident(_) => 4,
+        }
+    }
+}
+
+/// Compares an integer and a real exactly, without rounding the integer through f64.
+fn compare_integer_real(i: i64, r: f64) -> Option<Ordering> {
+    if r.is_nan() {
+        return None;
+    }
+    if r >= 9223372036854775808.0 {
+        return Some(Ordering::Less);
+    }
+    if r < -9223372036854775808.0 {
+        return Some(Ordering::Greater);
+    }
+    let truncated = r.trunc();
+    match i.cmp(&(truncated as i64)) {
+        Ordering::Equal => 0.0.partial_cmp(&(r - truncated)),
+        ordering => Some(ordering),
+    }
+}
+
+impl PartialOrd for Literal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Ident(a), Self::Ident(b)) => a.partial_cmp(b),
+            (Self::StringLiteral(a), Self::StringLiteral(b)) => a.partial_cmp(b),
+            (Self::IntegerLiteral(a), Self::IntegerLiteral(b)) => a.partial_cmp(b),
+            (Self::RealLiteral(a), Self::RealLiteral(b)) => a.partial_cmp(b),
+            (Self::IntegerLiteral(a), Self::RealLiteral(b)) => compare_integer_real(*a, *b),
+            (Self::RealLiteral(a), Self::IntegerLiteral(b)) => compare_integer_real(*b, *a).map(Ordering::reverse),
+            (Self::BlobLiteral(a), Self::BlobLiteral(b)) => a.partial_cmp(b),
+            _ => self.rank().partial_cmp(&other.rank()),
         }
-        Err(anyhow!("Literal isnt an ident"))
     }
 }
 
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+/// Formats a real the way sqlite3 prints it (printf "%!.15g"): 15 significant digits and always a decimal point.
+pub fn format_real(n: f64) -> String {
+    if n.is_infinite() {
+        return if n > 0.0 { "Inf".into() } else { "-Inf".into() };
+    }
+    if n == 0.0 {
+        return "0.0".into();
+    }
+    let scientific = format!("{:.14e}", n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if !(-4..15).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs())
+    } else {
+        trim_fraction(&format!("{:.*}", (14 - exponent) as usize, n))
+    }
+}
+
+/// Drops trailing zeros from a decimal fraction while keeping at least one digit after the point.
+fn trim_fraction(number: &str) -> String {
+    if !number.contains('.') {
+        return format!("{}.0", number);
+    }
+    let mut trimmed = number.trim_end_matches('0').to_string();
+    if trimmed.ends_with('.') {
+        trimmed.push('0');
+    }
+    trimmed
+}
+
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Ident(s) => write!(f, "{}", s),
             Self::StringLiteral(s) => write!(f, "{}", s),
-            Self::NumberLiteral(n) => write!(f, "{}", n),
+            Self::IntegerLiteral(n) => write!(f, "{}", n),
+            Self::RealLiteral(n) => write!(f, "{}", format_real(*n)),
+            Self::BlobLiteral(b) => {
+                write!(f, "X'")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "'")
+            },
             Self::Null => write!(f, "null"),
         }
     }
@@ -50,13 +349,174 @@ impl fmt::Display for Literal {
 #[derive(Debug, Clone, PartialEq)]
 pub enum SelectItem {
     Star,
-    Count,
-    Literal(Literal),
+    /// An expression, the result column name, which is its alias or else its text as written, and the alias
+    Expr { expr: Expr, name: String, alias: Option<String> },
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SelectStatement {
+    /// Whether duplicate result rows are dropped
+    pub distinct: bool,
     pub columns: Vec<SelectItem>,
+    /// The first table of the FROM clause, and the name it goes by in the query
+    pub table: String,
+    pub table_alias: Option<String>,
+    /// Tables joined to the first, in order
+    pub joins: Vec<Join>,
+    pub where_expr: Option<Expr>,
+    /// Expressions or result column numbers whose values split the rows into groups
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    /// Most rows to return, no limit when it is negative
+    pub limit: Option<Expr>,
+    /// Rows to skip before the first one returned
+    pub offset: Option<Expr>,
+}
+
+/// A table of the FROM clause after the first, joined with `,`, `JOIN`, `INNER JOIN` or `CROSS JOIN`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub table: String,
+    pub alias: Option<String>,
+    /// The ON condition, which for an inner join is just another WHERE term
+    pub constraint: Option<Expr>,
+}
+
+/// One key of an ORDER BY clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub collation: Option<String>,
+    pub order: SortOrder,
+    /// `Some(true)` for NULLS FIRST, `Some(false)` for NULLS LAST
+    pub nulls_first: Option<bool>,
+}
+
+impl OrderingTerm {
+    /// Whether NULLs sort before other values, by default they are the smallest values.
+    pub fn nulls_first(&self) -> bool {
+        self.nulls_first.unwrap_or(self.order == SortOrder::Asc)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Parsed `CREATE TABLE` statement, as stored in sqlite_schema.sql
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTableStatement {
+    pub name: String,
+    pub columns: Vec<ColumnDefinition>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDefinition {
+    pub name: String,
+    pub type_name: Option<String>,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        order: Option<SortOrder>,
+        autoincrement: bool,
+    },
+    NotNull,
+    Unique,
+    Check(String),
+    Default(DefaultValue),
+    Collate(String),
+    References(ForeignKey),
+    Generated {
+        expr: String,
+        stored: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue {
+    Literal(Literal),
+    /// Parenthesised expressions and CURRENT_TIME/CURRENT_DATE/CURRENT_TIMESTAMP, kept as SQL text
+    Expression(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<String>),
+    Unique(Vec<String>),
+    Check(String),
+    ForeignKey {
+        columns: Vec<String>,
+        references: ForeignKey,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+/// Parsed `CREATE INDEX` statement, as stored in sqlite_schema.sql
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexStatement {
+    pub name: String,
     pub table: String,
-    pub where_expr: Option<Expr>
+    pub unique: bool,
+    pub columns: Vec<IndexedColumn>,
+    /// Condition of a partial index
+    pub where_expr: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+    pub key: IndexKey,
+    pub collation: Option<String>,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexKey {
+    Column(String),
+    /// Expression indexes, kept as SQL text
+    Expression(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reals_format_like_sqlite3() {
+        for (value, text) in [
+            (1.0, "1.0"),
+            (-2.5, "-2.5"),
+            (0.1, "0.1"),
+            (1.0 / 3.0, "0.333333333333333"),
+            (0.1 + 0.2, "0.3"),
+            (100.0, "100.0"),
+            (123456789012345.0, "123456789012345.0"),
+            (999999999999999.0, "999999999999999.0"),
+            (1234567890123456.0, "1.23456789012346e+15"),
+            (1e15, "1.0e+15"),
+            (2.0 / 3.0 * 1e20, "6.66666666666667e+19"),
+            (1e100, "1.0e+100"),
+            (0.0001, "0.0001"),
+            (0.00001, "1.0e-05"),
+            (-1.5e-10, "-1.5e-10"),
+            (0.0, "0.0"),
+            (-0.0, "0.0"),
+            (f64::INFINITY, "Inf"),
+            (f64::NEG_INFINITY, "-Inf"),
+        ] {
+            assert_eq!(format_real(value), text, "format of {:e}", value);
+        }
+    }
 }
@@ -0,0 +1,535 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, bail};
+
+use crate::modules::{ast::{Literal, SortOrder}, header::TextEncoding, helpers::{CellKind, compare_in_encoding, get_varint, parse_record, read_payload}, pager::Pager};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeKind {
+    /// Rows keyed by rowid, entries only live in leaf pages
+    Table,
+    /// Records ordered by their columns, interior cells are entries too
+    Index,
+}
+
+/// A b-tree page with its header decoded.
+struct Page {
+    buffer: Vec<u8>,
+    header_offset: usize,
+    is_leaf: bool,
+    cell_num: usize,
+    right_child: u32,
+}
+
+impl Page {
+    fn load(pager: &Pager, page_num: u32, kind: TreeKind) -> Result<Self> {
+        let buffer = pager.read_page(page_num)?;
+        // the first page starts with the database header
+        let header_offset = if page_num == 1 { 100 } else { 0 };
+        let page_type = buffer[header_offset];
+        let is_leaf = match (kind, page_type) {
+            (TreeKind::Table, 13) | (TreeKind::Index, 10) => true,
+            (TreeKind::Table, 5) | (TreeKind::Index, 2) => false,
+            _ => bail!("Unrecognized {:?} page type {} on page {}", kind, page_type, page_num),
+        };
+        let cell_num = u16::from_be_bytes([buffer[header_offset+3], buffer[header_offset+4]]) as usize;
+        let right_child = if is_leaf {
+            0
+        } else {
+            u32::from_be_bytes([buffer[header_offset+8], buffer[header_offset+9], buffer[header_offset+10], buffer[header_offset+11]])
+        };
+        Ok(Self { buffer, header_offset, is_leaf, cell_num, right_child })
+    }
+
+    fn cell_offset(&self, i: usize) -> usize {
+        let pointer = self.header_offset + if self.is_leaf { 8 } else { 12 } + 2*i;
+        u16::from_be_bytes([self.buffer[pointer], self.buffer[pointer+1]]) as usize
+    }
+
+    /// Child page left of cell `i`, or the right-most child when `i` is the cell count.
+    fn child(&self, i: usize) -> u32 {
+        if i == self.cell_num {
+            return self.right_child;
+        }
+        let offset = self.cell_offset(i);
+        u32::from_be_bytes([self.buffer[offset], self.buffer[offset+1], self.buffer[offset+2], self.buffer[offset+3]])
+    }
+
+    /// Rowid key of a table cell. For interior cells it is the largest rowid of the left child.
+    fn rowid(&self, i: usize) -> i64 {
+        let mut current_offset = self.cell_offset(i);
+        if self.is_leaf {
+            get_varint(&self.buffer, &mut current_offset); // size of record
+        } else {
+            current_offset += 4;
+        }
+        get_varint(&self.buffer, &mut current_offset)
+    }
+
+    fn record(&self, pager: &Pager, kind: TreeKind, i: usize) -> Result<Vec<Literal>> {
+        let mut current_offset = self.cell_offset(i);
+        if !self.is_leaf {
+            current_offset += 4;
+        }
+        let payload_size = get_varint(&self.buffer, &mut current_offset) as usize;
+        let cell_kind = match kind {
+            TreeKind::Table => {
+                get_varint(&self.buffer, &mut current_offset); // the rowid
+                CellKind::TableLeaf
+            },
+            TreeKind::Index => CellKind::Index,
+        };
+        let payload = read_payload(pager, &self.buffer, current_offset, payload_size, cell_kind)?;
+        parse_record(&payload, pager.text_encoding)
+    }
+}
+
+/// A page on the path from the root to the current entry, and which cell or child of it we are at.
+struct Frame {
+    page: Page,
+    index: usize,
+}
+
+/// Walks the entries of a table or index b-tree in key order, keeping the path from the root on an
+/// explicit stack so it can move in both directions and stop at any point.
+///
+/// The cursor is positioned on an entry when [`BTreeCursor::is_valid`] is true. That is a leaf cell,
+/// or for index trees also an interior cell once its left child has been visited.
+pub struct BTreeCursor<'a> {
+    pager: &'a Pager,
+    root: u32,
+    kind: TreeKind,
+    /// Sort order of each index column, DESC columns are stored in descending order
+    orders: Vec<SortOrder>,
+    stack: Vec<Frame>,
+}
+
+impl<'a> BTreeCursor<'a> {
+    pub fn table(pager: &'a Pager, root: u32) -> Self {
+        Self { pager, root, kind: TreeKind::Table, orders: vec![], stack: vec![] }
+    }
+
+    pub fn index(pager: &'a Pager, root: u32, orders: Vec<SortOrder>) -> Self {
+        Self { pager, root, kind: TreeKind::Index, orders, stack: vec![] }
+    }
+
+    /// Encoding of the text in the records the cursor reads.
+    pub fn encoding(&self) -> TextEncoding {
+        self.pager.text_encoding
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.stack.last().is_some_and(|frame| {
+            frame.index < frame.page.cell_num && (frame.page.is_leaf || self.kind == TreeKind::Index)
+        })
+    }
+
+    /// Moves to the first entry, returns false if the tree is empty.
+    pub fn first(&mut self) -> Result<bool> {
+        self.stack.clear();
+        self.descend_leftmost(self.root)?;
+        Ok(self.is_valid())
+    }
+
+    /// Moves to the last entry, returns false if the tree is empty.
+    pub fn last(&mut self) -> Result<bool> {
+        self.stack.clear();
+        self.descend_rightmost(self.root)?;
+        Ok(self.is_valid())
+    }
+
+    /// Moves to the following entry, returns false once past the last one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<bool> {
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(false);
+        };
+        if !frame.page.is_leaf {
+            // on an interior index entry, continue with the subtree right of it
+            frame.index += 1;
+            let child = frame.page.child(frame.index);
+            self.descend_leftmost(child)?;
+            return Ok(self.is_valid());
+        }
+        frame.index += 1;
+        if frame.index < frame.page.cell_num {
+            return Ok(true);
+        }
+        // leaf exhausted, climb until there is something right of the child we came from
+        loop {
+            self.stack.pop();
+            let Some(parent) = self.stack.last_mut() else {
+                return Ok(false);
+            };
+            if parent.index == parent.page.cell_num {
+                continue;
+            }
+            if self.kind == TreeKind::Index {
+                return Ok(true); // the interior cell itself comes next
+            }
+            parent.index += 1;
+            let child = parent.page.child(parent.index);
+            self.descend_leftmost(child)?;
+            return Ok(self.is_valid());
+        }
+    }
+
+    /// Moves to the preceding entry, returns false once before the first one.
+    pub fn prev(&mut self) -> Result<bool> {
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(false);
+        };
+        if !frame.page.is_leaf {
+            // on an interior index entry, the previous one is the largest of its left child
+            let child = frame.page.child(frame.index);
+            self.descend_rightmost(child)?;
+            return Ok(self.is_valid());
+        }
+        if frame.index > 0 {
+            frame.index -= 1;
+            return Ok(true);
+        }
+        // leaf exhausted, climb until there is something left of the child we came from
+        loop {
+            self.stack.pop();
+            let Some(parent) = self.stack.last_mut() else {
+                return Ok(false);
+            };
+            if parent.index == 0 {
+                continue;
+            }
+            parent.index -= 1;
+            if self.kind == TreeKind::Index {
+                return Ok(true); // the interior cell left of the child comes before it
+            }
+            let child = parent.page.child(parent.index);
+            self.descend_rightmost(child)?;
+            return Ok(self.is_valid());
+        }
+    }
+
+    /// Table trees: moves to the first row whose rowid is at least `rowid`.
+    pub fn seek_rowid(&mut self, rowid: i64) -> Result<bool> {
+        self.stack.clear();
+        let mut page_num = self.root;
+        loop {
+            let page = Page::load(self.pager, page_num, self.kind)?;
+            // interior keys are the largest rowid of their left child
+            let index = (0..page.cell_num).find(|&i| page.rowid(i) >= rowid).unwrap_or(page.cell_num);
+            let is_leaf = page.is_leaf;
+            let child = if is_leaf { 0 } else { page.child(index) };
+            self.stack.push(Frame { page, index });
+            if is_leaf {
+                return self.settle_after_seek();
+            }
+            page_num = child;
+        }
+    }
+
+    /// Index trees: moves to the first entry whose leading columns are at least `key`.
+    pub fn seek(&mut self, key: &[Literal]) -> Result<bool> {
+        self.stack.clear();
+        let mut page_num = self.root;
+        loop {
+            let page = Page::load(self.pager, page_num, self.kind)?;
+            let mut index = page.cell_num;
+            for i in 0..page.cell_num {
+                let record = page.record(self.pager, self.kind, i)?;
+                if self.compare_key(&record, key) != Ordering::Less {
+                    index = i;
+                    break;
+                }
+            }
+            let is_leaf = page.is_leaf;
+            let child = if is_leaf { 0 } else { page.child(index) };
+            self.stack.push(Frame { page, index });
+            if is_leaf {
+                return self.settle_after_seek();
+            }
+            page_num = child;
+        }
+    }
+
+    /// Rowid of the current entry, the last column of an index record.
+    pub fn rowid(&self) -> Result<i64> {
+        let Some(frame) = self.stack.last() else {
+            bail!("Cursor is not on an entry")
+        };
+        match self.kind {
+            TreeKind::Table => Ok(frame.page.rowid(frame.index)),
+            TreeKind::Index => match self.record()?.last() {
+                Some(Literal::IntegerLiteral(rowid)) => Ok(*rowid),
+                _ => bail!("Index record does not end with a rowid"),
+            },
+        }
+    }
+
+    /// Decoded record of the current entry.
+    pub fn record(&self) -> Result<Vec<Literal>> {
+        let Some(frame) = self.stack.last().filter(|_| self.is_valid()) else {
+            bail!("Cursor is not on an entry")
+        };
+        frame.page.record(self.pager, self.kind, frame.index)
+    }
+
+    /// Compares the leading columns of an index `record` with `key`, in the order they are stored.
+    pub fn compare_key(&self, record: &[Literal], key: &[Literal]) -> Ordering {
+        for (i, (value, wanted)) in record.iter().zip(key).enumerate() {
+            let mut ordering = compare_in_encoding(value, wanted, self.pager.text_encoding).unwrap_or(Ordering::Equal);
+            if self.orders.get(i) == Some(&SortOrder::Desc) {
+                ordering = ordering.reverse();
+            }
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn descend_leftmost(&mut self, mut page_num: u32) -> Result<()> {
+        loop {
+            let page = Page::load(self.pager, page_num, self.kind)?;
+            let child = if page.is_leaf { None } else { Some(page.child(0)) };
+            self.stack.push(Frame { page, index: 0 });
+            match child {
+                Some(child) => page_num = child,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn descend_rightmost(&mut self, mut page_num: u32) -> Result<()> {
+        loop {
+            let page = Page::load(self.pager, page_num, self.kind)?;
+            if page.is_leaf {
+                // an empty root leaf leaves the cursor invalid
+                let index = page.cell_num.saturating_sub(1);
+                self.stack.push(Frame { page, index });
+                return Ok(());
+            }
+            let child = page.child(page.cell_num);
+            let index = page.cell_num;
+            self.stack.push(Frame { page, index });
+            page_num = child;
+        }
+    }
+
+    /// After a seek lands past the end of a leaf, moves on to the next entry in key order.
+    fn settle_after_seek(&mut self) -> Result<bool> {
+        let frame = self.stack.last_mut().unwrap();
+        if frame.index < frame.page.cell_num {
+            return Ok(true);
+        }
+        if frame.page.cell_num == 0 {
+            return Ok(false);
+        }
+        frame.index = frame.page.cell_num - 1;
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::{self, File}, path::PathBuf};
+
+    use super::*;
+    use crate::modules::{header::DatabaseHeader, helpers::{encode_record, put_varint}};
+
+    const PAGE_SIZE: usize = 512;
+
+    /// Pages of a database file built by hand, page 1 holding just the database header.
+    struct Pages(Vec<Vec<u8>>);
+
+    impl Pages {
+        fn new() -> Self {
+            let mut header = vec![0; PAGE_SIZE];
+            header[..16].copy_from_slice(b"SQLite format 3\0");
+            header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+            header[21..24].copy_from_slice(&[64, 32, 32]);
+            header[56..60].copy_from_slice(&1u32.to_be_bytes());
+            Self(vec![header])
+        }
+
+        /// Adds a b-tree page of the given type holding `cells`, returns its page number.
+        fn add(&mut self, page_type: u8, cells: &[Vec<u8>], right_child: Option<u32>) -> u32 {
+            let mut page = vec![0; PAGE_SIZE];
+            page[0] = page_type;
+            page[3..5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+            let mut pointer = 8;
+            if let Some(right_child) = right_child {
+                page[8..12].copy_from_slice(&right_child.to_be_bytes());
+                pointer = 12;
+            }
+            let mut content = PAGE_SIZE;
+            for cell in cells {
+                content -= cell.len();
+                page[content..content + cell.len()].copy_from_slice(cell);
+                page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+                pointer += 2;
+            }
+            assert!(pointer <= content, "cells overflow the page");
+            page[5..7].copy_from_slice(&(content as u16).to_be_bytes());
+            self.0.push(page);
+            self.0.len() as u32
+        }
+
+        /// A table tree of `depth` interior levels with `fanout` children each over leaves of
+        /// `per_leaf` rows, with rowids from `first` on. Returns the root page and the last rowid.
+        fn table(&mut self, depth: usize, fanout: usize, per_leaf: i64, first: i64) -> (u32, i64) {
+            if depth == 0 {
+                let cells = (first..first + per_leaf).map(|rowid| {
+                    let payload = encode_record(&[Literal::IntegerLiteral(rowid * 10)]);
+                    let mut cell = vec![];
+                    put_varint(&mut cell, payload.len() as u64);
+                    put_varint(&mut cell, rowid as u64);
+                    cell.extend(payload);
+                    cell
+                }).collect::<Vec<_>>();
+                return (self.add(13, &cells, None), first + per_leaf - 1);
+            }
+            let mut cells = vec![];
+            let mut next = first;
+            let mut right_child = 0;
+            for i in 0..fanout {
+                let (child, last) = self.table(depth - 1, fanout, per_leaf, next);
+                next = last + 1;
+                if i + 1 == fanout {
+                    right_child = child;
+                } else {
+                    let mut cell = child.to_be_bytes().to_vec();
+                    put_varint(&mut cell, last as u64);
+                    cells.push(cell);
+                }
+            }
+            (self.add(5, &cells, Some(right_child)), next - 1)
+        }
+
+        /// An index tree of the same shape, where each interior cell holds the entry between the
+        /// children either side of it. Returns the root page and the last key.
+        fn index(&mut self, depth: usize, fanout: usize, per_leaf: i64, first: i64) -> (u32, i64) {
+            let cell = |key: i64, child: Option<u32>| {
+                let payload = encode_record(&[Literal::IntegerLiteral(key), Literal::IntegerLiteral(key)]);
+                let mut cell = child.map_or(vec![], |child| child.to_be_bytes().to_vec());
+                put_varint(&mut cell, payload.len() as u64);
+                cell.extend(payload);
+                cell
+            };
+            if depth == 0 {
+                let cells = (first..first + per_leaf).map(|key| cell(key, None)).collect::<Vec<_>>();
+                return (self.add(10, &cells, None), first + per_leaf - 1);
+            }
+            let mut cells = vec![];
+            let mut next = first;
+            let mut right_child = 0;
+            for i in 0..fanout {
+                let (child, last) = self.index(depth - 1, fanout, per_leaf, next);
+                if i + 1 == fanout {
+                    right_child = child;
+                    next = last + 1;
+                } else {
+                    cells.push(cell(last + 1, Some(child)));
+                    next = last + 2;
+                }
+            }
+            (self.add(2, &cells, Some(right_child)), next - 1)
+        }
+
+        fn open(&self, name: &str) -> (Pager, TempFile) {
+            let path = std::env::temp_dir().join(format!("codecrafters-sqlite-btree-{}-{}", std::process::id(), name));
+            fs::write(&path, self.0.concat()).unwrap();
+            let header = DatabaseHeader::parse(self.0[0][..100].try_into().unwrap()).unwrap();
+            (Pager::new(File::open(&path).unwrap(), &header).unwrap(), TempFile(path))
+        }
+    }
+
+    struct TempFile(PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Key of the entry under the cursor: the rowid of a table, the first column of an index.
+    fn key(cursor: &BTreeCursor) -> i64 {
+        match cursor.kind {
+            TreeKind::Table => cursor.rowid().unwrap(),
+            TreeKind::Index => match cursor.record().unwrap()[0] {
+                Literal::IntegerLiteral(key) => key,
+                ref value => panic!("unexpected key {:?}", value),
+            },
+        }
+    }
+
+    /// Walks the whole tree both ways, and steps back and forth at every entry, which crosses
+    /// every leaf and interior boundary.
+    fn check_walks(mut cursor: BTreeCursor, last: i64) {
+        let mut keys = vec![];
+        let mut valid = cursor.first().unwrap();
+        while valid {
+            let current = key(&cursor);
+            keys.push(current);
+            if current > 1 {
+                assert!(cursor.prev().unwrap());
+                assert_eq!(key(&cursor), current - 1);
+                assert!(cursor.next().unwrap());
+                assert_eq!(key(&cursor), current);
+            }
+            valid = cursor.next().unwrap();
+        }
+        assert_eq!(keys, (1..=last).collect::<Vec<_>>());
+
+        keys.clear();
+        let mut valid = cursor.last().unwrap();
+        while valid {
+            let current = key(&cursor);
+            keys.push(current);
+            if current < last {
+                assert!(cursor.next().unwrap());
+                assert_eq!(key(&cursor), current + 1);
+                assert!(cursor.prev().unwrap());
+                assert_eq!(key(&cursor), current);
+            }
+            valid = cursor.prev().unwrap();
+        }
+        assert_eq!(keys, (1..=last).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn table_cursor_crosses_page_boundaries() {
+        for (depth, name) in [(0, "table-leaf"), (1, "table-2"), (2, "table-3")] {
+            let mut pages = Pages::new();
+            let (root, last) = pages.table(depth, 3, 4, 1);
+            let (pager, _file) = pages.open(name);
+            check_walks(BTreeCursor::table(&pager, root), last);
+
+            let mut cursor = BTreeCursor::table(&pager, root);
+            // each rowid, in particular the last one of every leaf, then the entry before it
+            for rowid in 1..=last {
+                assert!(cursor.seek_rowid(rowid).unwrap());
+                assert_eq!(cursor.rowid().unwrap(), rowid);
+                assert_eq!(cursor.record().unwrap()[0], Literal::IntegerLiteral(rowid * 10));
+                assert_eq!(cursor.prev().unwrap(), rowid > 1);
+            }
+            assert!(!cursor.seek_rowid(last + 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn index_cursor_crosses_page_boundaries() {
+        for (depth, name) in [(0, "index-leaf"), (1, "index-2"), (2, "index-3")] {
+            let mut pages = Pages::new();
+            let (root, last) = pages.index(depth, 3, 4, 1);
+            let (pager, _file) = pages.open(name);
+            check_walks(BTreeCursor::index(&pager, root, vec![SortOrder::Asc]), last);
+
+            let mut cursor = BTreeCursor::index(&pager, root, vec![SortOrder::Asc]);
+            for wanted in 1..=last {
+                assert!(cursor.seek(&[Literal::IntegerLiteral(wanted)]).unwrap());
+                assert_eq!(key(&cursor), wanted);
+                assert_eq!(cursor.rowid().unwrap(), wanted);
+            }
+            assert!(!cursor.seek(&[Literal::IntegerLiteral(last + 1)]).unwrap());
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::Result;
+
+use crate::modules::{ast::Literal, executor::Operator, header::DatabaseHeader, pager::Pager, planner::plan_select, schema::Schema, sorter::DEFAULT_SORT_MEMORY, sql_parser::sql_parser};
+
+/// An open database file.
+pub struct Connection {
+    pager: Pager,
+    header: DatabaseHeader,
+    schema: Schema,
+    /// Bytes a sort, GROUP BY or DISTINCT may hold in memory before it spills to temporary files
+    sort_memory_budget: usize,
+}
+
+impl Connection {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0; 100];
+        file.read_exact(&mut header)?;
+        let header = DatabaseHeader::parse(&header)?;
+        let pager = Pager::new(file, &header)?;
+        let schema = Schema::load(&pager)?;
+        Ok(Self { pager, header, schema, sort_memory_budget: DEFAULT_SORT_MEMORY })
+    }
+
+    pub fn header(&self) -> &DatabaseHeader {
+        &self.header
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Page reader, for walking b-trees directly with a [`BTreeCursor`](crate::BTreeCursor).
+    pub fn pager(&self) -> &Pager {
+        &self.pager
+    }
+
+    /// Sets how many bytes of rows ORDER BY, GROUP BY and DISTINCT keep in memory, beyond that rows
+    /// are sorted in temporary files.
+    pub fn set_sort_memory_budget(&mut self, bytes: usize) {
+        self.sort_memory_budget = bytes;
+    }
+
+    /// The bytes reserved at the end of a page by extensions (header byte 20).
+    pub fn reserved_region(&self, page_num: u32) -> Result<Vec<u8>> {
+        self.pager.reserved_region(page_num)
+    }
+
+    /// Parses `sql` and builds its query plan against the schema.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        let select_stmt = sql_parser::statement(sql)?;
+        let plan = plan_select(&self.pager, &self.schema, &select_stmt, self.sort_memory_budget)?;
+        Ok(Statement { root: plan.root, column_names: plan.column_names })
+    }
+}
+
+/// A prepared SELECT statement.
+pub struct Statement<'conn> {
+    /// Operator producing the result rows
+    root: Box<dyn Operator + 'conn>,
+    column_names: Vec<String>,
+}
+
+impl<'conn> Statement<'conn> {
+    pub fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    /// Starts executing the statement, rows are read from the database as the iterator advances.
+    pub fn query(&mut self) -> Result<Rows<'_>> {
+        self.root.rewind()?;
+        Ok(Rows { root: self.root.as_mut(), done: false })
+    }
+}
+
+/// Result rows of a statement.
+pub struct Rows<'a> {
+    root: &'a mut (dyn Operator + 'a),
+    done: bool,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let row = self.root.next().map(|values| values.map(|values| Row { values })).transpose();
+        // stop after the last row or the first error
+        self.done = !matches!(row, Some(Ok(_)));
+        row
+    }
+}
+
+/// One result row, with a value per selected column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    values: Vec<Literal>,
+}
+
+impl Row {
+    pub fn get(&self, index: usize) -> Option<&Literal> {
+        self.values.get(index)
+    }
+
+    pub fn values(&self) -> &[Literal] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Literal> {
+        self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::testing;
+
+    #[test]
+    fn prepares_and_reruns_statements() {
+        let connection = testing::open("query");
+        let mut statement = connection.prepare("SELECT id, name AS owner FROM owners WHERE item_id = 3").unwrap();
+        assert_eq!(statement.column_names(), ["id", "owner"]);
+        let rows = statement.query().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[0].get(0), Some(Literal::IntegerLiteral(1))));
+        assert!(matches!(rows[1].get(1), Some(Literal::StringLiteral(name)) if name == "bob"));
+        assert!(rows[0].get(2).is_none());
+        assert_eq!(rows[1].values().len(), 2);
+        // querying again starts over from the first row
+        let again = statement.query().unwrap().map(|row| row.unwrap().into_values()).collect::<Vec<_>>();
+        assert_eq!(format!("{:?}", again), format!("{:?}", rows.into_iter().map(Row::into_values).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn rows_are_read_lazily() {
+        let connection = testing::open("query");
+        let mut statement = connection.prepare("SELECT id FROM items").unwrap();
+        let first = statement.query().unwrap().take(3).map(|row| row.unwrap().values()[0].to_string()).collect::<Vec<_>>();
+        assert_eq!(first, ["1", "2", "3"]);
+        assert_eq!(statement.query().unwrap().count(), 300);
+    }
+
+    #[test]
+    fn reports_prepare_errors() {
+        let connection = testing::open("query");
+        let error = |sql| connection.prepare(sql).err().unwrap().to_string();
+        assert_eq!(error("SELECT nosuch FROM items"), "no such column: nosuch");
+        assert_eq!(error("SELECT id FROM missing"), "Table not found");
+        assert!(connection.prepare("SELEC id FROM items").is_err());
+    }
+}
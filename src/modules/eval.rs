@@ -0,0 +1,585 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::modules::{ast::{AggregateCall, BinaryOp, CompareOp, Expr, Literal, UnaryOp}, functions::{Context, ScalarFunction}, header::TextEncoding, helpers::compare_in_encoding, table::{Affinity, ROWID_NAMES, Table}};
+
+/// Names of the values in the rows an operator produces, used to resolve column references.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    /// One entry per row value, `None` for the hidden rowid of a table
+    names: Vec<Option<String>>,
+    /// Affinity of each value when it comes straight from a table column
+    affinities: Vec<Option<Affinity>>,
+    /// Collation of each value when it comes straight from a table column
+    collations: Vec<Option<Collation>>,
+    /// Text is compared as bytes in the database encoding, like the BINARY collation
+    encoding: TextEncoding,
+    /// Aggregate calls whose values follow the named values in each row
+    aggregates: Vec<AggregateCall>,
+}
+
+impl Scope {
+    pub fn new(names: Vec<String>, encoding: TextEncoding) -> Self {
+        let (affinities, collations) = (vec![None; names.len()], vec![None; names.len()]);
+        Self { names: names.into_iter().map(Some).collect(), affinities, collations, encoding, aggregates: vec![] }
+    }
+
+    /// Rows of `table` as produced by [`Table::row_from_record`]: its columns then the rowid.
+    pub fn of_table(table: &Table, encoding: TextEncoding) -> Self {
+        let mut names = table.columns.iter().map(|column| Some(column.name.clone())).collect::<Vec<_>>();
+        let mut affinities = table.columns.iter().map(|column| Some(column.affinity())).collect::<Vec<_>>();
+        // unknown collations are application-defined, and compare like BINARY here
+        let mut collations = table.columns.iter()
+            .map(|column| Some(column.collation.as_deref().and_then(Collation::from_name).unwrap_or(Collation::Binary)))
+            .collect::<Vec<_>>();
+        names.push(None);
+        affinities.push(Some(Affinity::Integer));
+        collations.push(Some(Collation::Binary));
+        Self { names, affinities, collations, encoding, aggregates: vec![] }
+    }
+
+    /// Rows made of a row of `left` followed by a row of `right`.
+    pub fn join(left: &Scope, right: &Scope) -> Self {
+        Self {
+            names: left.names.iter().chain(&right.names).cloned().collect(),
+            affinities: left.affinities.iter().chain(&right.affinities).cloned().collect(),
+            collations: left.collations.iter().chain(&right.collations).cloned().collect(),
+            encoding: left.encoding,
+            aggregates: vec![],
+        }
+    }
+
+    /// The same values named `qualifier.name`, as the columns of a table in a join are, with the
+    /// hidden rowid called `qualifier.rowid`.
+    pub fn qualified(self, qualifier: &str) -> Self {
+        let names = self.names.iter().map(|name| Some(format!("{}.{}", qualifier, name.as_deref().unwrap_or("rowid")))).collect();
+        Self { names, ..self }
+    }
+
+    /// Rows of `input` followed by the values of `aggregates`, as produced by [`Aggregate`](crate::Aggregate).
+    pub fn aggregated(input: &Scope, aggregates: Vec<AggregateCall>) -> Self {
+        Self { aggregates, ..input.clone() }
+    }
+
+    /// Number of values in a row before the aggregate values.
+    pub fn width(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Position of the value called `name`, columns shadow the rowid pseudo-columns.
+    pub fn resolve(&self, name: &str) -> Result<usize> {
+        if let Some(i) = self.names.iter().position(|n| n.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(name))) {
+            return Ok(i);
+        }
+        if ROWID_NAMES.iter().any(|rowid| rowid.eq_ignore_ascii_case(name)) {
+            if let Some(i) = self.names.iter().position(Option::is_none) {
+                return Ok(i);
+            }
+        }
+        Err(anyhow!("no such column: {}", name))
+    }
+
+    /// Affinity of `expr` when it is a bare column reference, other expressions have none.
+    pub fn affinity(&self, expr: &Expr) -> Option<Affinity> {
+        match expr {
+            Expr::Literal(Literal::Ident(name)) => self.resolve(name).ok().and_then(|i| self.affinities[i]),
+            _ => None,
+        }
+    }
+
+    /// Collation of `expr` when it is a bare column reference, other expressions have none.
+    pub fn collation(&self, expr: &Expr) -> Option<Collation> {
+        match expr {
+            Expr::Literal(Literal::Ident(name)) => self.resolve(name).ok().and_then(|i| self.collations[i]),
+            _ => None,
+        }
+    }
+
+    /// Checks every column reference in `expr` up front, so a bad query fails before reading any row.
+    pub fn check(&self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Literal(Literal::Ident(name)) => self.resolve(name).map(|_| ()),
+            Expr::Literal(_) => Ok(()),
+            Expr::Compare { left, right, .. } | Expr::Is { left, right, .. } | Expr::And(left, right) | Expr::Or(left, right) => {
+                self.check(left)?;
+                self.check(right)
+            },
+            Expr::Between { expr, low, high, .. } => {
+                self.check(expr)?;
+                self.check(low)?;
+                self.check(high)
+            },
+            Expr::In { expr, list, .. } => {
+                self.check(expr)?;
+                list.iter().try_for_each(|item| self.check(item))
+            },
+            Expr::Not(expr) | Expr::Unary { expr, .. } => self.check(expr),
+            Expr::Binary { left, right, .. } => {
+                self.check(left)?;
+                self.check(right)
+            },
+            Expr::Aggregate(call) => {
+                let name = call.function.name();
+                if !self.aggregates.contains(call) {
+                    bail!("misuse of aggregate function {}()", name)
+                }
+                if !call.function.arity().contains(&call.args.len()) {
+                    bail!("wrong number of arguments to function {}()", name)
+                }
+                if call.distinct && call.args.len() != 1 {
+                    bail!("DISTINCT aggregates must have exactly one argument")
+                }
+                // the arguments are evaluated on the input rows, where no aggregate has a value yet
+                let input = Self { aggregates: vec![], ..self.clone() };
+                call.args.iter().try_for_each(|arg| input.check(arg))
+            },
+            Expr::Function(call) => {
+                let function = ScalarFunction::lookup(&call.name).ok_or(anyhow!("no such function: {}", call.name))?;
+                if !function.arity.contains(&call.args.len()) {
+                    bail!("wrong number of arguments to function {}()", call.name)
+                }
+                call.args.iter().try_for_each(|arg| self.check(arg))
+            },
+        }
+    }
+
+    pub fn eval(&self, expr: &Expr, row: &[Literal]) -> Result<Literal> {
+        match expr {
+            Expr::Literal(Literal::Ident(name)) => Ok(row[self.resolve(name)?].clone()),
+            Expr::Literal(literal) => Ok(literal.clone()),
+            Expr::Compare { op, left, right } => Ok(boolean(self.compare(*op, left, right, row)?)),
+            Expr::Is { left: left_expr, right: right_expr, negated } => {
+                let (left, right) = self.operands(left_expr, right_expr, row)?;
+                let equal = match (&left, &right) {
+                    (Literal::Null, Literal::Null) => true,
+                    (Literal::Null, _) | (_, Literal::Null) => false,
+                    _ => self.order(&left, &right, self.comparison_collation(left_expr, right_expr)) == Some(Ordering::Equal),
+                };
+                Ok(boolean(Some(equal != *negated)))
+            },
+            Expr::Between { expr, low, high, negated } => {
+                let between = and(self.compare(CompareOp::Ge, expr, low, row)?, self.compare(CompareOp::Le, expr, high, row)?);
+                Ok(boolean(between.map(|between| between != *negated)))
+            },
+            Expr::In { expr, list, negated } => {
+                let mut found = Some(false);
+                for item in list {
+                    match self.compare(CompareOp::Eq, expr, item, row)? {
+                        Some(true) => {
+                            found = Some(true);
+                            break;
+                        },
+                        Some(false) => {},
+                        None => found = None,
+                    }
+                }
+                Ok(boolean(found.map(|found| found != *negated)))
+            },
+            // three-valued logic: NULL is unknown, so it only decides the result when the other side does not
+            Expr::And(left, right) => {
+                let left = truth(&self.eval(left, row)?);
+                if left == Some(false) {
+                    return Ok(boolean(left));
+                }
+                Ok(boolean(and(left, truth(&self.eval(right, row)?))))
+            },
+            Expr::Or(left, right) => {
+                let left = truth(&self.eval(left, row)?);
+                if left == Some(true) {
+                    return Ok(boolean(left));
+                }
+                Ok(boolean(or(left, truth(&self.eval(right, row)?))))
+            },
+            Expr::Not(expr) => Ok(boolean(truth(&self.eval(expr, row)?).map(|value| !value))),
+            Expr::Binary { op, left, right } => Ok(binary(*op, self.eval(left, row)?, self.eval(right, row)?)),
+            Expr::Unary { op: UnaryOp::Negate, expr } => Ok(binary(BinaryOp::Subtract, Literal::IntegerLiteral(0), self.eval(expr, row)?)),
+            Expr::Unary { op: UnaryOp::Plus, expr } => self.eval(expr, row),
+            Expr::Unary { op: UnaryOp::BitNot, expr } => Ok(match self.eval(expr, row)? {
+                Literal::Null => Literal::Null,
+                value => Literal::IntegerLiteral(!integer(&value)),
+            }),
+            Expr::Aggregate(call) => match self.aggregates.iter().position(|aggregate| aggregate == call) {
+                Some(i) => Ok(row[self.names.len() + i].clone()),
+                None => bail!("misuse of aggregate function {}()", call.function.name()),
+            },
+            Expr::Function(call) => {
+                let function = ScalarFunction::lookup(&call.name).ok_or(anyhow!("no such function: {}", call.name))?;
+                let args = call.args.iter().map(|arg| self.eval(arg, row)).collect::<Result<Vec<_>>>()?;
+                let collation = call.args.iter().find_map(|arg| self.collation(arg)).unwrap_or(Collation::Binary);
+                function.call(&args, &Context { encoding: self.encoding, collation })
+            },
+        }
+    }
+
+    /// Compares two expressions with SQLite's rules, `None` when either side is NULL.
+    fn compare(&self, op: CompareOp, left_expr: &Expr, right_expr: &Expr, row: &[Literal]) -> Result<Option<bool>> {
+        let (left, right) = self.operands(left_expr, right_expr, row)?;
+        if matches!(left, Literal::Null) || matches!(right, Literal::Null) {
+            return Ok(None);
+        }
+        let collation = self.comparison_collation(left_expr, right_expr);
+        Ok(self.order(&left, &right, collation).map(|ordering| op.matches(ordering)))
+    }
+
+    /// The collation of the left operand if it is a column, else that of the right one.
+    fn comparison_collation(&self, left: &Expr, right: &Expr) -> Collation {
+        self.collation(left).or(self.collation(right)).unwrap_or(Collation::Binary)
+    }
+
+    /// Orders two values of these rows, NULL < numbers < text < blobs, with text compared by `collation`.
+    pub fn order(&self, left: &Literal, right: &Literal, collation: Collation) -> Option<Ordering> {
+        collation.compare(left, right, self.encoding)
+    }
+
+    /// Evaluates both sides of a comparison and converts them with the column affinities
+    /// (https://www.sqlite.org/datatype3.html#type_conversions_prior_to_comparison).
+    fn operands(&self, left: &Expr, right: &Expr, row: &[Literal]) -> Result<(Literal, Literal)> {
+        let (left_affinity, right_affinity) = (self.affinity(left), self.affinity(right));
+        let (mut left, mut right) = (self.eval(left, row)?, self.eval(right, row)?);
+        let numeric = |affinity: Option<Affinity>| matches!(affinity, Some(Affinity::Integer | Affinity::Real | Affinity::Numeric));
+        if numeric(left_affinity) && !numeric(right_affinity) {
+            right = Affinity::Numeric.apply(right);
+        } else if numeric(right_affinity) && !numeric(left_affinity) {
+            left = Affinity::Numeric.apply(left);
+        } else if left_affinity == Some(Affinity::Text) && right_affinity.is_none() {
+            right = Affinity::Text.apply(right);
+        } else if right_affinity == Some(Affinity::Text) && left_affinity.is_none() {
+            left = Affinity::Text.apply(left);
+        }
+        Ok((left, right))
+    }
+}
+
+/// Built-in text collating sequences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collation {
+    /// Bytes of the text in the database encoding
+    Binary,
+    /// Like BINARY after folding ASCII letters to lower case
+    NoCase,
+    /// Like BINARY ignoring trailing spaces
+    RTrim,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "BINARY" => Some(Self::Binary),
+            "NOCASE" => Some(Self::NoCase),
+            "RTRIM" => Some(Self::RTrim),
+            _ => None,
+        }
+    }
+
+    /// Only BINARY exists for UTF-16, SQLite compares with the other two after converting text to UTF-8.
+    pub fn compare(self, left: &Literal, right: &Literal, encoding: TextEncoding) -> Option<Ordering> {
+        match (self, left, right) {
+            (Self::NoCase, Literal::StringLiteral(left), Literal::StringLiteral(right)) => Some(left.to_ascii_lowercase().cmp(&right.to_ascii_lowercase())),
+            (Self::RTrim, Literal::StringLiteral(left), Literal::StringLiteral(right)) => Some(left.trim_end_matches(' ').cmp(right.trim_end_matches(' '))),
+            _ => compare_in_encoding(left, right, encoding),
+        }
+    }
+}
+
+/// Result of an arithmetic, concatenation or bitwise operator, NULL when either side is. Integer
+/// arithmetic that overflows is redone with reals, and dividing by zero gives NULL.
+fn binary(op: BinaryOp, left: Literal, right: Literal) -> Literal {
+    if matches!(left, Literal::Null) || matches!(right, Literal::Null) {
+        return Literal::Null;
+    }
+    match op {
+        BinaryOp::Concat => return Literal::StringLiteral(text(&left) + &text(&right)),
+        BinaryOp::BitAnd => return Literal::IntegerLiteral(integer(&left) & integer(&right)),
+        BinaryOp::BitOr => return Literal::IntegerLiteral(integer(&left) | integer(&right)),
+        BinaryOp::ShiftLeft => return Literal::IntegerLiteral(shift(integer(&left), integer(&right))),
+        BinaryOp::ShiftRight => return Literal::IntegerLiteral(shift(integer(&left), integer(&right).saturating_neg())),
+        _ => {},
+    }
+    let (left, right) = (number(&left), number(&right));
+    if let (Literal::IntegerLiteral(a), Literal::IntegerLiteral(b)) = (&left, &right) {
+        let (a, b) = (*a, *b);
+        let exact = match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Subtract => a.checked_sub(b),
+            BinaryOp::Multiply => a.checked_mul(b),
+            BinaryOp::Divide if b == 0 => return Literal::Null,
+            BinaryOp::Divide => a.checked_div(b),
+            BinaryOp::Remainder if b == 0 => return Literal::Null,
+            // i64::MIN % -1 overflows, and is 0 like any remainder of -1
+            _ => Some(a % if b == -1 { 1 } else { b }),
+        };
+        if let Some(n) = exact {
+            return Literal::IntegerLiteral(n);
+        }
+    }
+    let real = |value: &Literal| match value {
+        Literal::IntegerLiteral(n) => *n as f64,
+        Literal::RealLiteral(n) => *n,
+        _ => 0.0,
+    };
+    let (a, b) = (real(&left), real(&right));
+    let result = match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Subtract => a - b,
+        BinaryOp::Multiply => a * b,
+        BinaryOp::Divide if b == 0.0 => return Literal::Null,
+        BinaryOp::Divide => a / b,
+        // a real remainder is the remainder of the integer parts
+        _ => match (a as i64, b as i64) {
+            (_, 0) => return Literal::Null,
+            (a, b) => (a % if b == -1 { 1 } else { b }) as f64,
+        },
+    };
+    if result.is_nan() { Literal::Null } else { Literal::RealLiteral(result) }
+}
+
+/// `value` shifted left by `amount` bits, or right when `amount` is negative, keeping the sign.
+fn shift(value: i64, amount: i64) -> i64 {
+    match amount {
+        64.. => 0,
+        0..=63 => ((value as u64) << amount) as i64,
+        -63..=-1 => value >> -amount,
+        _ => if value < 0 { -1 } else { 0 },
+    }
+}
+
+/// A number for arithmetic: text counts as the number it starts with, which is an integer unless
+/// written with a fraction or exponent.
+pub(crate) fn number(value: &Literal) -> Literal {
+    let text = match value {
+        Literal::IntegerLiteral(_) | Literal::RealLiteral(_) => return value.clone(),
+        Literal::StringLiteral(s) | Literal::Ident(s) => s.clone(),
+        Literal::BlobLiteral(b) => String::from_utf8_lossy(b).into_owned(),
+        Literal::Null => return Literal::Null,
+    };
+    match numeric_prefix(&text) {
+        "" => Literal::IntegerLiteral(0),
+        prefix => match prefix.parse() {
+            Ok(n) => Literal::IntegerLiteral(n),
+            Err(_) => Literal::RealLiteral(prefix.parse().unwrap_or(0.0)),
+        },
+    }
+}
+
+/// An integer for the bitwise operators and function arguments: reals are truncated, text counts
+/// as the integer its leading digits spell.
+pub(crate) fn integer(value: &Literal) -> i64 {
+    match value {
+        Literal::IntegerLiteral(n) => *n,
+        // `as` saturates out of range and takes NaN to 0
+        Literal::RealLiteral(n) => *n as i64,
+        Literal::StringLiteral(s) | Literal::Ident(s) => leading_integer(s),
+        Literal::BlobLiteral(b) => leading_integer(&String::from_utf8_lossy(b)),
+        Literal::Null => 0,
+    }
+}
+
+/// Integer spelled by the optional sign and digits `text` starts with after spaces, saturating.
+fn leading_integer(text: &str) -> i64 {
+    let text = text.trim_start();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    text.bytes().take_while(u8::is_ascii_digit).fold(0i64, |n, digit| {
+        let digit = i64::from(digit - b'0');
+        if negative { n.saturating_mul(10).saturating_sub(digit) } else { n.saturating_mul(10).saturating_add(digit) }
+    })
+}
+
+/// Text of a value for concatenation and string functions.
+pub(crate) fn text(value: &Literal) -> String {
+    match value {
+        Literal::BlobLiteral(b) => String::from_utf8_lossy(b).into_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// SQL value of a truth value: 1, 0 or NULL.
+fn boolean(value: Option<bool>) -> Literal {
+    match value {
+        Some(value) => Literal::IntegerLiteral(value as i64),
+        None => Literal::Null,
+    }
+}
+
+fn and(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether a value counts as true in a WHERE clause: non-zero numbers, and text that starts with one.
+pub fn is_true(value: &Literal) -> bool {
+    match value {
+        Literal::IntegerLiteral(n) => *n != 0,
+        Literal::RealLiteral(n) => *n != 0.0,
+        Literal::StringLiteral(s) | Literal::Ident(s) => leading_number(s) != 0.0,
+        Literal::BlobLiteral(_) | Literal::Null => false,
+    }
+}
+
+/// Truth value of `value`, `None` when it is NULL.
+fn truth(value: &Literal) -> Option<bool> {
+    match value {
+        Literal::Null => None,
+        value => Some(is_true(value)),
+    }
+}
+
+/// Numeric value of the longest prefix of `text` that reads as a number, 0 if there is none.
+pub(crate) fn leading_number(text: &str) -> f64 {
+    numeric_prefix(text).parse().unwrap_or(0.0)
+}
+
+/// Longest prefix of `text` after leading spaces that reads as a number, maybe empty: a sign,
+/// digits with an optional fraction, then an optional exponent.
+fn numeric_prefix(text: &str) -> &str {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let digits_from = |start: usize| start + bytes[start..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+    let mut end = digits_from(usize::from(matches!(bytes.first(), Some(b'+' | b'-'))));
+    let mut mantissa_digits = end > 0 && bytes[end - 1].is_ascii_digit();
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits_from(end + 1);
+        mantissa_digits |= fraction_end > end + 1;
+        end = fraction_end;
+    }
+    if !mantissa_digits {
+        return "";
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        let exponent_end = digits_from(end + 1 + sign);
+        if exponent_end > end + 1 + sign {
+            end = exponent_end;
+        }
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::testing;
+
+    #[test]
+    fn numeric_prefixes() {
+        for (text, prefix) in [
+            ("  12abc", "12"),
+            ("-0x10", "-0"),
+            (" +.5e1z", "+.5e1"),
+            ("5.", "5."),
+            ("1e", "1"),
+            ("1e+", "1"),
+            ("2E-3x", "2E-3"),
+            (".e3", ""),
+            ("+.", ""),
+            ("-", ""),
+            ("é1", ""),
+            ("3é", "3"),
+        ] {
+            assert_eq!(numeric_prefix(text), prefix, "prefix of {:?}", text);
+        }
+    }
+
+    #[test]
+    fn numbers_from_text() {
+        // equality of literals compares numbers across types, so match the variants
+        assert!(matches!(number(&Literal::StringLiteral("12abc".into())), Literal::IntegerLiteral(12)));
+        assert!(matches!(number(&Literal::StringLiteral("1.5e1x".into())), Literal::RealLiteral(15.0)));
+        assert!(matches!(number(&Literal::StringLiteral("1e999".into())), Literal::RealLiteral(n) if n == f64::INFINITY));
+        assert!(matches!(number(&Literal::StringLiteral("x".into())), Literal::IntegerLiteral(0)));
+        assert_eq!(leading_number(&"9".repeat(100_000)), f64::INFINITY);
+    }
+
+    #[test]
+    fn blobs_compare_after_text() {
+        let connection = testing::open("types");
+        assert_eq!(testing::query(&connection, "SELECT id, v FROM vals WHERE v = x'00FF10'"), ["20|X'00ff10'"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v > 'zzz'"), ["19", "20"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v < x'' AND v > 'text'"), Vec::<String>::new());
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals ORDER BY v DESC LIMIT 4"), ["20", "19", "17", "18"]);
+        let sql = "SELECT hex(v), length(v), typeof(v) FROM vals WHERE typeof(v) = 'blob'";
+        assert_eq!(testing::query(&connection, sql), ["|0|blob", "00FF10|3|blob"]);
+    }
+
+    #[test]
+    fn integers_keep_64_bits() {
+        let connection = testing::open("types");
+        let sql = "SELECT v, typeof(v) FROM vals WHERE id IN (6, 7, 9, 10, 11, 12, 13, 14, 16)";
+        assert_eq!(testing::query(&connection, sql), [
+            "8388607|integer",
+            "-8388608|integer",
+            "140737488355327|integer",
+            "-140737488355328|integer",
+            "9007199254740993|integer",
+            "9223372036854775807|integer",
+            "-9223372036854775808|integer",
+            "2.5|real",
+            "1.0e+100|real",
+        ]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v = 9007199254740993"), ["11"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v > 9007199254740992 AND v < 9007199254740994"), ["11"]);
+        assert_eq!(testing::query(&connection, "SELECT v + 1, v / 2, v / 2.0 FROM vals WHERE id = 11"), ["9007199254740994|4503599627370496|4.5035996273705e+15"]);
+    }
+
+    #[test]
+    fn logic_is_three_valued() {
+        let connection = testing::open("query");
+        let sql = "SELECT id, item_id > 5 AND name > 'c', item_id > 5 OR name > 'c', NOT item_id > 5, item_id > 5 AND 0, item_id > 5 OR 1 FROM owners";
+        assert_eq!(testing::query(&connection, sql), ["1|0|0|1|0|1", "2|0|0|1|0|1", "3|1|1|0|0|1", "4|1|1|0|0|1", "5|null|1|null|0|1"]);
+        // a NULL condition drops the row, negated or not
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE NOT (item_id = 3 OR item_id = 10)"), ["4"]);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let connection = testing::open("query");
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE id = 1 OR id = 2 AND name = 'x'"), ["1"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE (id = 1 OR id = 2) AND name = 'bob'"), ["2"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE NOT id = 1 AND id < 3"), ["2"]);
+    }
+
+    #[test]
+    fn between_in_and_is() {
+        let connection = testing::open("query");
+        let ids = |condition: &str| testing::query(&connection, &format!("SELECT id FROM owners WHERE {}", condition)).join(",");
+        assert_eq!(ids("item_id BETWEEN 3 AND 10"), "1,2,3");
+        assert_eq!(ids("item_id NOT BETWEEN 3 AND 10"), "4");
+        assert_eq!(ids("item_id IN (10, 999, null)"), "3,4");
+        assert_eq!(ids("item_id IS NULL"), "5");
+        assert_eq!(ids("item_id IS NOT 3"), "3,4,5");
+        let sql = "SELECT id, item_id NOT IN (3, null), item_id IN (3, null) FROM owners";
+        assert_eq!(testing::query(&connection, sql), ["1|0|1", "2|0|1", "3|null|null", "4|null|null", "5|null|null"]);
+        let sql = "SELECT id, item_id = NULL, item_id IS NULL, NULL IS NULL FROM owners WHERE id > 3";
+        assert_eq!(testing::query(&connection, sql), ["4|null|0|1", "5|null|1|1"]);
+    }
+
+    #[test]
+    fn comparisons_apply_column_affinity() {
+        let connection = testing::open("query");
+        // text compared with an INTEGER column is converted to a number first
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE item_id = '10'"), ["3"]);
+        // numbers sort before text, text before blobs
+        assert_eq!(testing::query(&connection, "SELECT id FROM owners WHERE name > 10 AND name < 'c'"), ["1", "2"]);
+        let connection = testing::open("types");
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v < 0").join(","), "5,7,10,13,15");
+        // without affinity only text and blobs compare above text
+        assert_eq!(testing::query(&connection, "SELECT id FROM vals WHERE v >= 'a'").join(","), "17,19,20");
+        assert_eq!(testing::query(&connection, "SELECT count(*) FROM vals WHERE v > 2.4 AND v < 2.6"), ["1"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM keyed WHERE user_id = '10'"), ["first"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM keyed WHERE user_id IN ('5', 20)"), ["third", "second"]);
+    }
+}
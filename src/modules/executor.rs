@@ -0,0 +1,858 @@
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, ops::RangeInclusive};
+
+use anyhow::Result;
+
+use crate::modules::{aggregate::{Group, HashKey}, ast::{AggregateCall, Expr, Literal, SortOrder}, btree::BTreeCursor, eval::{Collation, Scope, is_true}, header::TextEncoding, sorter::{DEFAULT_SORT_MEMORY, SortedRows, Sorter, row_size}, table::Table};
+
+/// A step of a query plan. Operators pull rows from their inputs one at a time, so a plan only
+/// reads as much of the database as its consumer asks for.
+pub trait Operator {
+    /// Produces the next row, or `None` once there are no more.
+    fn next(&mut self) -> Result<Option<Vec<Literal>>>;
+
+    /// Starts over from the first row, so the operator can be read again.
+    fn rewind(&mut self) -> Result<()>;
+}
+
+/// Every row of a table in rowid order, or the reverse, laid out as [`Table::row_from_record`] returns them.
+pub struct TableScan<'a> {
+    table: &'a Table,
+    cursor: BTreeCursor<'a>,
+    reverse: bool,
+    started: bool,
+}
+
+impl<'a> TableScan<'a> {
+    pub fn new(table: &'a Table, cursor: BTreeCursor<'a>) -> Self {
+        Self { table, cursor, reverse: false, started: false }
+    }
+
+    /// Reads the rows from the largest rowid down.
+    pub fn reversed(mut self) -> Self {
+        self.reverse = !self.reverse;
+        self
+    }
+}
+
+impl Operator for TableScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        let valid = match (self.started, self.reverse) {
+            (false, false) => self.cursor.first()?,
+            (false, true) => self.cursor.last()?,
+            (true, false) => self.cursor.next()?,
+            (true, true) => self.cursor.prev()?,
+        };
+        self.started = true;
+        if !valid {
+            return Ok(None);
+        }
+        let rowid = self.cursor.rowid()?;
+        Ok(Some(self.table.row_from_record(rowid, self.cursor.record()?, self.cursor.encoding())?))
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.started = false;
+        Ok(())
+    }
+}
+
+/// Table rows whose rowid lies in one of `ranges`, which are sorted and disjoint.
+pub struct RowidScan<'a> {
+    table: &'a Table,
+    cursor: BTreeCursor<'a>,
+    ranges: Vec<RangeInclusive<i64>>,
+    reverse: bool,
+    /// Range being read
+    current: usize,
+    started: bool,
+}
+
+impl<'a> RowidScan<'a> {
+    pub fn new(table: &'a Table, cursor: BTreeCursor<'a>, ranges: Vec<RangeInclusive<i64>>) -> Self {
+        Self { table, cursor, ranges, reverse: false, current: 0, started: false }
+    }
+
+    /// Reads the rows from the largest rowid down.
+    pub fn reversed(mut self) -> Self {
+        self.ranges.reverse();
+        self.reverse = !self.reverse;
+        self
+    }
+
+    /// Moves to the last row with a rowid of at most `rowid`.
+    fn seek_rowid_back(&mut self, rowid: i64) -> Result<bool> {
+        if !self.cursor.seek_rowid(rowid)? {
+            return self.cursor.last();
+        }
+        if self.cursor.rowid()? > rowid {
+            return self.cursor.prev();
+        }
+        Ok(true)
+    }
+}
+
+impl Operator for RowidScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        while let Some(range) = self.ranges.get(self.current).cloned() {
+            let valid = match (self.started, self.reverse) {
+                (false, false) => self.cursor.seek_rowid(*range.start())?,
+                (false, true) => self.seek_rowid_back(*range.end())?,
+                (true, false) => self.cursor.next()?,
+                (true, true) => self.cursor.prev()?,
+            };
+            self.started = true;
+            if valid {
+                let rowid = self.cursor.rowid()?;
+                if range.contains(&rowid) {
+                    return Ok(Some(self.table.row_from_record(rowid, self.cursor.record()?, self.cursor.encoding())?));
+                }
+            }
+            self.current += 1;
+            self.started = false;
+        }
+        Ok(None)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.current = 0;
+        self.started = false;
+        Ok(())
+    }
+}
+
+/// One end of a range of index keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBound {
+    /// Values of the leading index columns
+    pub key: Vec<Literal>,
+    pub inclusive: bool,
+}
+
+/// Table rows whose index entries lie between `lower` and `upper`, found through the index and
+/// looked up by rowid. Bounds are in the order entries are stored, so for a DESC column `lower`
+/// holds the largest value. An equality is a range with the same inclusive key at both ends.
+pub struct IndexScan<'a> {
+    table: &'a Table,
+    index_cursor: BTreeCursor<'a>,
+    table_cursor: BTreeCursor<'a>,
+    lower: Option<KeyBound>,
+    upper: Option<KeyBound>,
+    reverse: bool,
+    started: bool,
+}
+
+impl<'a> IndexScan<'a> {
+    pub fn new(table: &'a Table, index_cursor: BTreeCursor<'a>, table_cursor: BTreeCursor<'a>, lower: Option<KeyBound>, upper: Option<KeyBound>) -> Self {
+        Self { table, index_cursor, table_cursor, lower, upper, reverse: false, started: false }
+    }
+
+    /// Walks the range from the upper bound down.
+    pub fn reversed(mut self) -> Self {
+        self.reverse = !self.reverse;
+        self
+    }
+
+    /// Moves the index cursor to the last entry before the upper bound.
+    fn seek_upper(&mut self) -> Result<bool> {
+        let Some(upper) = &self.upper else {
+            return self.index_cursor.last();
+        };
+        let mut valid = self.index_cursor.seek(&upper.key)?;
+        while valid && upper.inclusive && self.index_cursor.compare_key(&self.index_cursor.record()?, &upper.key) == Ordering::Equal {
+            valid = self.index_cursor.next()?;
+        }
+        // now on the first entry past the bound, if there is one
+        if valid { self.index_cursor.prev() } else { self.index_cursor.last() }
+    }
+
+    /// Whether the current entry lies outside the range, on the side the scan is heading to.
+    fn past_end(&self) -> Result<bool> {
+        let (bound, beyond) = if self.reverse { (&self.lower, Ordering::Less) } else { (&self.upper, Ordering::Greater) };
+        let Some(bound) = bound else {
+            return Ok(false);
+        };
+        let ordering = self.index_cursor.compare_key(&self.index_cursor.record()?, &bound.key);
+        Ok(ordering == beyond || (ordering == Ordering::Equal && !bound.inclusive))
+    }
+
+    /// Moves the index cursor to the first entry past the lower bound.
+    fn seek_lower(&mut self) -> Result<bool> {
+        let Some(lower) = &self.lower else {
+            return self.index_cursor.first();
+        };
+        let mut valid = self.index_cursor.seek(&lower.key)?;
+        while valid && !lower.inclusive && self.index_cursor.compare_key(&self.index_cursor.record()?, &lower.key) == Ordering::Equal {
+            valid = self.index_cursor.next()?;
+        }
+        Ok(valid)
+    }
+}
+
+impl Operator for IndexScan<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        loop {
+            let valid = match (self.started, self.reverse) {
+                (false, false) => self.seek_lower()?,
+                (false, true) => self.seek_upper()?,
+                (true, false) => self.index_cursor.next()?,
+                (true, true) => self.index_cursor.prev()?,
+            };
+            self.started = true;
+            if !valid || self.past_end()? {
+                return Ok(None);
+            }
+            let rowid = self.index_cursor.rowid()?;
+            if self.table_cursor.seek_rowid(rowid)? && self.table_cursor.rowid()? == rowid {
+                return Ok(Some(self.table.row_from_record(rowid, self.table_cursor.record()?, self.table_cursor.encoding())?));
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.started = false;
+        Ok(())
+    }
+}
+
+/// Rows of `input` for which `predicate` is true.
+pub struct Filter<'a> {
+    input: Box<dyn Operator + 'a>,
+    predicate: Expr,
+    scope: Scope,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, predicate: Expr, scope: Scope) -> Self {
+        Self { input, predicate, scope }
+    }
+}
+
+impl Operator for Filter<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        while let Some(row) = self.input.next()? {
+            if is_true(&self.scope.eval(&self.predicate, &row)?) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.input.rewind()
+    }
+}
+
+/// Evaluates `exprs` against each row of `input`.
+pub struct Project<'a> {
+    input: Box<dyn Operator + 'a>,
+    exprs: Vec<Expr>,
+    scope: Scope,
+}
+
+impl<'a> Project<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, exprs: Vec<Expr>, scope: Scope) -> Self {
+        Self { input, exprs, scope }
+    }
+}
+
+impl Operator for Project<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        let Some(row) = self.input.next()? else {
+            return Ok(None);
+        };
+        let values = self.exprs.iter().map(|expr| self.scope.eval(expr, &row)).collect::<Result<_>>()?;
+        Ok(Some(values))
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.input.rewind()
+    }
+}
+
+/// A value to order rows by and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub order: SortOrder,
+    pub nulls_first: bool,
+    pub collation: Collation,
+}
+
+impl SortKey {
+    pub(crate) fn compare(&self, scope: &Scope, a: &Literal, b: &Literal) -> Ordering {
+        match (a, b) {
+            (Literal::Null, Literal::Null) => Ordering::Equal,
+            // NULLs stay on their side whatever the direction
+            (Literal::Null, _) => if self.nulls_first { Ordering::Less } else { Ordering::Greater },
+            (_, Literal::Null) => if self.nulls_first { Ordering::Greater } else { Ordering::Less },
+            _ => {
+                let ordering = scope.order(a, b, self.collation).unwrap_or(Ordering::Equal);
+                if self.order == SortOrder::Desc { ordering.reverse() } else { ordering }
+            },
+        }
+    }
+}
+
+/// Reads all of `input` and returns it ordered by `keys`, rows with equal keys keep their input order.
+/// Rows beyond the memory budget are sorted in runs on disk and merged.
+pub struct Sort<'a> {
+    input: Box<dyn Operator + 'a>,
+    keys: Vec<SortKey>,
+    scope: Scope,
+    memory_budget: usize,
+    /// Sorted rows, filled on the first call to `next`
+    sorted: Option<SortedRows>,
+}
+
+impl<'a> Sort<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, keys: Vec<SortKey>, scope: Scope) -> Self {
+        Self { input, keys, scope, memory_budget: DEFAULT_SORT_MEMORY, sorted: None }
+    }
+
+    /// Bytes of rows to hold in memory before spilling them to a temporary file.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    fn sort_input(&mut self) -> Result<SortedRows> {
+        let mut sorter = Sorter::new(self.keys.clone(), self.scope.clone(), self.memory_budget);
+        while let Some(row) = self.input.next()? {
+            let key = self.keys.iter().map(|key| self.scope.eval(&key.expr, &row)).collect::<Result<Vec<_>>>()?;
+            sorter.push(key, row)?;
+        }
+        sorter.finish()
+    }
+}
+
+impl Operator for Sort<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        if self.sorted.is_none() {
+            self.sorted = Some(self.sort_input()?);
+        }
+        self.sorted.as_mut().map_or(Ok(None), SortedRows::next_row)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.sorted = None;
+        self.input.rewind()
+    }
+}
+
+/// Drops rows equal to an earlier one: values are compared under the collation of their column, and
+/// NULLs are equal to each other. Rows come out in input order.
+///
+/// The rows returned so far are remembered in a hash set. Once that outgrows the memory budget, the
+/// rest of the input is de-duplicated by sorting it instead.
+pub struct Distinct<'a> {
+    input: Box<dyn Operator + 'a>,
+    collations: Vec<Collation>,
+    streaming: bool,
+    memory_budget: usize,
+    /// Keys of the rows returned so far, or when streaming of the last one only
+    seen: HashSet<Vec<HashKey>>,
+    /// Rough size of the rows in `seen`
+    seen_size: usize,
+    /// The de-duplicated rest of the input, once `seen` is full
+    spilled: Option<SortedRows>,
+}
+
+impl<'a> Distinct<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, collations: Vec<Collation>) -> Self {
+        Self { input, collations, streaming: false, memory_budget: DEFAULT_SORT_MEMORY, seen: HashSet::new(), seen_size: 0, spilled: None }
+    }
+
+    /// De-duplicates an input that returns equal rows one after the other, like a walk of an index
+    /// on the selected columns, without remembering every row.
+    pub fn streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    /// Bytes of rows to remember in memory before sorting the rest of the input.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    fn key(&self, row: &[Literal]) -> Vec<HashKey> {
+        row.iter().zip(&self.collations).map(|(value, collation)| HashKey::new(value, *collation)).collect()
+    }
+
+    /// Sorts `row` and the rest of the input to drop the duplicates and the rows returned already,
+    /// then sorts what is left back into input order.
+    fn sort_rest(&mut self, row: Vec<Literal>) -> Result<SortedRows> {
+        // the rows are their own keys, and any order that brings equal rows together will do
+        let scope = Scope::new(vec![], TextEncoding::Utf8);
+        let key = |collation| SortKey { expr: Expr::Literal(Literal::Null), order: SortOrder::Asc, nulls_first: true, collation };
+        let mut by_value = Sorter::new(self.collations.iter().map(|collation| key(*collation)).collect(), scope.clone(), self.memory_budget);
+        let mut position = 0;
+        let mut next = Some(row);
+        while let Some(mut row) = next {
+            if !self.seen.contains(&self.key(&row)) {
+                let values = row.clone();
+                row.push(Literal::IntegerLiteral(position));
+                by_value.push(values, row)?;
+            }
+            position += 1;
+            next = self.input.next()?;
+        }
+        // the sort is stable, so the first row of each run of equal rows is the earliest
+        let mut by_position = Sorter::new(vec![key(Collation::Binary)], scope, self.memory_budget);
+        let mut sorted = by_value.finish()?;
+        let mut last = None;
+        while let Some(mut row) = sorted.next_row()? {
+            let position = row.pop().unwrap_or(Literal::Null);
+            let key = self.key(&row);
+            if last.as_ref() != Some(&key) {
+                by_position.push(vec![position], row)?;
+                last = Some(key);
+            }
+        }
+        by_position.finish()
+    }
+}
+
+impl Operator for Distinct<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        if let Some(rows) = &mut self.spilled {
+            return rows.next_row();
+        }
+        while let Some(row) = self.input.next()? {
+            let key = self.key(&row);
+            if self.seen.contains(&key) {
+                continue;
+            }
+            if self.streaming {
+                self.seen.clear();
+            } else if self.seen_size + row_size(&row) > self.memory_budget {
+                let mut rows = self.sort_rest(row)?;
+                let row = rows.next_row();
+                self.spilled = Some(rows);
+                return row;
+            }
+            self.seen_size += row_size(&row);
+            self.seen.insert(key);
+            return Ok(Some(row));
+        }
+        Ok(None)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.seen.clear();
+        (self.seen_size, self.spilled) = (0, None);
+        self.input.rewind()
+    }
+}
+
+/// Skips the first `offset` rows of `input`, then returns at most `limit` rows.
+pub struct Limit<'a> {
+    input: Box<dyn Operator + 'a>,
+    limit: Option<u64>,
+    offset: u64,
+    /// Rows read from the input so far, including the skipped ones
+    position: u64,
+}
+
+impl<'a> Limit<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, limit: Option<u64>, offset: u64) -> Self {
+        Self { input, limit, offset, position: 0 }
+    }
+}
+
+impl Operator for Limit<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        if self.limit.is_some_and(|limit| self.position.saturating_sub(self.offset) >= limit) {
+            return Ok(None); // stop without reading any further
+        }
+        while self.position < self.offset {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.position += 1;
+        }
+        let row = self.input.next()?;
+        if row.is_some() {
+            self.position += 1;
+        }
+        Ok(row)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.position = 0;
+        self.input.rewind()
+    }
+}
+
+/// Folds the rows of `input` into one row per group of rows with equal `group_by` values, or into
+/// a single row when there is no GROUP BY. Each output row is an input row of the group, for the
+/// bare columns of the query, followed by the value of each aggregate call.
+///
+/// Groups are collected in a hash table and returned ordered by their key, unless the input is
+/// known to arrive grouped, in which case each group is returned as soon as it ends. When the
+/// groups outgrow the memory budget, the input is read again sorted by group and streamed.
+pub struct Aggregate<'a> {
+    input: Box<dyn Operator + 'a>,
+    group_by: Vec<Expr>,
+    calls: Vec<AggregateCall>,
+    scope: Scope,
+    streaming: bool,
+    memory_budget: usize,
+    /// The input sorted by group, once the hash table outgrew the memory budget
+    sorted: Option<SortedRows>,
+    /// Rows of the groups left to return, filled on the first call to `next` unless streaming
+    groups: Option<std::vec::IntoIter<Vec<Literal>>>,
+    /// When streaming, the key and first row of the next group, read while looking for the end of the previous one
+    next_group: Option<(Vec<HashKey>, Vec<Literal>)>,
+    done: bool,
+}
+
+impl<'a> Aggregate<'a> {
+    /// `scope` describes the input rows, the output rows are described by [`Scope::aggregated`].
+    pub fn new(input: Box<dyn Operator + 'a>, group_by: Vec<Expr>, calls: Vec<AggregateCall>, scope: Scope) -> Self {
+        Self { input, group_by, calls, scope, streaming: false, memory_budget: DEFAULT_SORT_MEMORY, sorted: None, groups: None, next_group: None, done: false }
+    }
+
+    /// Aggregates an input that returns the rows of each group one after the other, like a walk of
+    /// an index on the grouping columns.
+    pub fn streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    /// Bytes of groups to hold in memory before sorting the input by group instead.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Values of the GROUP BY expressions for `row`, and the hash keys that decide which rows group together.
+    fn key(&self, row: &[Literal]) -> Result<(Vec<Literal>, Vec<HashKey>)> {
+        let values = self.group_by.iter().map(|expr| self.scope.eval(expr, row)).collect::<Result<Vec<_>>>()?;
+        let keys = values.iter().zip(&self.group_by)
+            .map(|(value, expr)| HashKey::new(value, self.scope.collation(expr).unwrap_or(Collation::Binary)))
+            .collect();
+        Ok((values, keys))
+    }
+
+    /// Order of the groups, which is also how the input is sorted to stream it.
+    fn sort_keys(&self) -> Vec<SortKey> {
+        self.group_by.iter().map(|expr| SortKey {
+            expr: expr.clone(),
+            order: SortOrder::Asc,
+            nulls_first: true,
+            collation: self.scope.collation(expr).unwrap_or(Collation::Binary),
+        }).collect()
+    }
+
+    /// Rows of every group in order, or `None` when they do not fit in the memory budget.
+    fn hash_groups(&mut self) -> Result<Option<Vec<Vec<Literal>>>> {
+        let mut positions = HashMap::new();
+        let mut groups: Vec<(Vec<Literal>, Group)> = vec![];
+        let mut size = 0;
+        while let Some(row) = self.input.next()? {
+            let (values, keys) = self.key(&row)?;
+            let i = match positions.get(&keys) {
+                Some(&i) => i,
+                None => {
+                    // the key is held twice, as values and in the hash table
+                    size += 2 * row_size(&values) + row_size(&row);
+                    if size > self.memory_budget && !self.group_by.is_empty() {
+                        return Ok(None);
+                    }
+                    groups.push((values, Group::new(&self.calls, &self.scope)));
+                    positions.insert(keys, groups.len() - 1);
+                    groups.len() - 1
+                },
+            };
+            groups[i].1.add(row, &self.calls, &self.scope)?;
+        }
+        if self.group_by.is_empty() && groups.is_empty() {
+            // without GROUP BY there is one row even when no input row matched
+            groups.push((vec![], Group::new(&self.calls, &self.scope)));
+        }
+        let keys = self.sort_keys();
+        groups.sort_by(|(a, _), (b, _)| {
+            a.iter().zip(b).zip(&keys)
+                .map(|((a, b), key)| key.compare(&self.scope, a, b))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        groups.into_iter().map(|(_, group)| group.finish(&self.scope)).collect::<Result<_>>().map(Some)
+    }
+
+    /// Reads the input again from the start, sorted by group.
+    fn sort_input(&mut self) -> Result<SortedRows> {
+        self.input.rewind()?;
+        let mut sorter = Sorter::new(self.sort_keys(), self.scope.clone(), self.memory_budget);
+        while let Some(row) = self.input.next()? {
+            sorter.push(self.key(&row)?.0, row)?;
+        }
+        sorter.finish()
+    }
+
+    fn next_input(&mut self) -> Result<Option<Vec<Literal>>> {
+        match &mut self.sorted {
+            Some(sorted) => sorted.next_row(),
+            None => self.input.next(),
+        }
+    }
+
+    fn next_streamed_group(&mut self) -> Result<Option<Vec<Literal>>> {
+        let (group_keys, first_row) = match self.next_group.take() {
+            Some(next_group) => next_group,
+            None => match self.next_input()? {
+                Some(row) => (self.key(&row)?.1, row),
+                None => return Ok(None),
+            },
+        };
+        let mut group = Group::new(&self.calls, &self.scope);
+        group.add(first_row, &self.calls, &self.scope)?;
+        while let Some(row) = self.next_input()? {
+            let keys = self.key(&row)?.1;
+            if keys != group_keys {
+                self.next_group = Some((keys, row));
+                break;
+            }
+            group.add(row, &self.calls, &self.scope)?;
+        }
+        group.finish(&self.scope).map(Some)
+    }
+}
+
+impl Operator for Aggregate<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if (self.streaming || self.sorted.is_some()) && !self.group_by.is_empty() {
+            let row = self.next_streamed_group()?;
+            self.done = row.is_none();
+            return Ok(row);
+        }
+        if self.groups.is_none() {
+            match self.hash_groups()? {
+                Some(groups) => self.groups = Some(groups.into_iter()),
+                None => {
+                    self.sorted = Some(self.sort_input()?);
+                    return self.next();
+                },
+            }
+        }
+        let row = self.groups.as_mut().and_then(Iterator::next);
+        self.done = row.is_none();
+        Ok(row)
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        (self.sorted, self.groups, self.next_group, self.done) = (None, None, None, false);
+        self.input.rewind()
+    }
+}
+
+/// Every row of `left` followed by every row of `right`, reading `right` again for each left row.
+/// A join condition is a [`Filter`] on top, over [`Scope::join`] of both sides.
+pub struct NestedLoopJoin<'a> {
+    left: Box<dyn Operator + 'a>,
+    right: Box<dyn Operator + 'a>,
+    current: Option<Vec<Literal>>,
+}
+
+impl<'a> NestedLoopJoin<'a> {
+    pub fn new(left: Box<dyn Operator + 'a>, right: Box<dyn Operator + 'a>) -> Self {
+        Self { left, right, current: None }
+    }
+}
+
+impl Operator for NestedLoopJoin<'_> {
+    fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+        loop {
+            if let Some(left_row) = &self.current {
+                if let Some(right_row) = self.right.next()? {
+                    return Ok(Some(left_row.iter().cloned().chain(right_row).collect()));
+                }
+            }
+            match self.left.next()? {
+                Some(left_row) => {
+                    self.current = Some(left_row);
+                    self.right.rewind()?;
+                },
+                None => {
+                    self.current = None;
+                    return Ok(None);
+                },
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        self.current = None;
+        self.left.rewind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::modules::ast::{AggregateFunction, BinaryOp, CompareOp};
+
+    /// Rows given up front.
+    struct Values {
+        rows: Vec<Vec<Literal>>,
+        position: usize,
+    }
+
+    impl Operator for Values {
+        fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+            self.position += 1;
+            Ok(self.rows.get(self.position - 1).cloned())
+        }
+
+        fn rewind(&mut self) -> Result<()> {
+            self.position = 0;
+            Ok(())
+        }
+    }
+
+    fn values<'a>(rows: Vec<Vec<Literal>>) -> Box<dyn Operator + 'a> {
+        Box::new(Values { rows, position: 0 })
+    }
+
+    /// Rows of `operator`, printed the way the CLI prints them.
+    fn collect(operator: &mut dyn Operator) -> Vec<String> {
+        let mut out = vec![];
+        while let Some(row) = operator.next().unwrap() {
+            out.push(row.iter().map(|value| value.to_string()).collect::<Vec<_>>().join("|"));
+        }
+        out
+    }
+
+    fn column(name: &str) -> Expr {
+        Expr::Literal(Literal::Ident(name.into()))
+    }
+
+    /// Rows of (k, v) where k takes a few values, 1 and 1.0 being the same group.
+    fn keyed_rows() -> Vec<Vec<Literal>> {
+        (0..200).map(|v| {
+            let k = match v % 5 {
+                0 => Literal::Null,
+                1 => Literal::StringLiteral("b".into()),
+                2 => Literal::StringLiteral("a".into()),
+                3 => Literal::IntegerLiteral(1),
+                _ => Literal::RealLiteral(1.0),
+            };
+            vec![k, Literal::IntegerLiteral(v)]
+        }).collect()
+    }
+
+    #[test]
+    fn spilled_group_by_matches_hash_and_streaming() {
+        let scope = Scope::new(vec!["k".into(), "v".into()], TextEncoding::Utf8);
+        let call = |function| AggregateCall { function, args: vec![column("v")], distinct: false };
+        let calls = vec![call(AggregateFunction::Count), call(AggregateFunction::Sum), call(AggregateFunction::GroupConcat)];
+        let aggregate = |input| Aggregate::new(input, vec![column("k")], calls.clone(), scope.clone());
+        let hashed = collect(&mut aggregate(values(keyed_rows())));
+        assert_eq!(hashed.len(), 4);
+        assert!(hashed[0].starts_with("null|0|40|3900|0,5,10,"));
+        assert!(hashed[1].starts_with("1|3|80|8080|3,4,8,9,"));
+        let spilled = collect(&mut aggregate(values(keyed_rows())).with_memory_budget(0));
+        assert_eq!(spilled, hashed);
+        let key = SortKey { expr: column("k"), order: SortOrder::Asc, nulls_first: true, collation: Collation::Binary };
+        let sorted = Box::new(Sort::new(values(keyed_rows()), vec![key], scope.clone()));
+        assert_eq!(collect(&mut aggregate(sorted).streaming()), hashed);
+    }
+
+    #[test]
+    fn spilled_distinct_keeps_input_order() {
+        let text = |s: &str| vec![Literal::StringLiteral(s.into())];
+        let rows = (0..50).flat_map(|_| [text("b"), text("A"), text("a"), vec![Literal::Null], text("c "), text("B"), text("c")]).collect::<Vec<_>>();
+        for memory_budget in [DEFAULT_SORT_MEMORY, 100, 0] {
+            let mut distinct = Distinct::new(values(rows.clone()), vec![Collation::NoCase]).with_memory_budget(memory_budget);
+            assert_eq!(collect(&mut distinct), ["b", "A", "null", "c ", "c"], "budget {}", memory_budget);
+            distinct.rewind().unwrap();
+            assert_eq!(collect(&mut distinct).len(), 5);
+        }
+    }
+
+    #[test]
+    fn nested_loop_join_pairs_every_row() {
+        let int = |n| vec![Literal::IntegerLiteral(n)];
+        let mut join = NestedLoopJoin::new(values(vec![int(1), int(2)]), values(vec![int(10), int(20), int(30)]));
+        let pairs = ["1|10", "1|20", "1|30", "2|10", "2|20", "2|30"];
+        assert_eq!(collect(&mut join), pairs);
+        join.rewind().unwrap();
+        assert_eq!(collect(&mut join), pairs);
+        let mut empty = NestedLoopJoin::new(values(vec![int(1)]), values(vec![]));
+        assert!(collect(&mut empty).is_empty());
+    }
+
+    #[test]
+    fn filter_and_project_rows() {
+        let scope = Scope::new(vec!["k".into(), "v".into()], TextEncoding::Utf8);
+        let predicate = Expr::Compare { op: CompareOp::Gt, left: Box::new(column("v")), right: Box::new(Expr::Literal(Literal::IntegerLiteral(195))) };
+        let filter = Box::new(Filter::new(values(keyed_rows()), predicate, scope.clone()));
+        let doubled = Expr::Binary { op: BinaryOp::Multiply, left: Box::new(column("v")), right: Box::new(Expr::Literal(Literal::IntegerLiteral(2))) };
+        let mut project = Project::new(filter, vec![doubled, column("k")], scope);
+        assert_eq!(collect(&mut project), ["392|b", "394|a", "396|1", "398|1.0"]);
+        project.rewind().unwrap();
+        assert_eq!(collect(&mut project).len(), 4);
+    }
+
+    #[test]
+    fn sort_orders_by_several_keys() {
+        let scope = Scope::new(vec!["k".into(), "v".into()], TextEncoding::Utf8);
+        let keys = vec![
+            SortKey { expr: column("k"), order: SortOrder::Desc, nulls_first: false, collation: Collation::Binary },
+            SortKey { expr: column("v"), order: SortOrder::Asc, nulls_first: true, collation: Collation::Binary },
+        ];
+        let rows = keyed_rows().into_iter().take(10).collect::<Vec<_>>();
+        let expected = ["b|1", "b|6", "a|2", "a|7", "1|3", "1.0|4", "1|8", "1.0|9", "null|0", "null|5"];
+        for memory_budget in [DEFAULT_SORT_MEMORY, 0] {
+            let mut sort = Sort::new(values(rows.clone()), keys.clone(), scope.clone()).with_memory_budget(memory_budget);
+            assert_eq!(collect(&mut sort), expected, "budget {}", memory_budget);
+            sort.rewind().unwrap();
+            assert_eq!(collect(&mut sort), expected);
+        }
+    }
+
+    #[test]
+    fn limit_skips_the_offset() {
+        let limit = |limit, offset| collect(&mut Limit::new(values(keyed_rows()), limit, offset));
+        assert_eq!(limit(Some(3), 0), ["null|0", "b|1", "a|2"]);
+        assert_eq!(limit(Some(2), 198), ["1|198", "1.0|199"]);
+        assert_eq!(limit(Some(5), 199), ["1.0|199"]);
+        assert_eq!(limit(None, 197).len(), 3);
+        assert!(limit(Some(0), 0).is_empty());
+    }
+
+    /// Passes rows through, counting how many were pulled.
+    struct Counted<'a> {
+        input: Box<dyn Operator + 'a>,
+        pulled: Rc<Cell<usize>>,
+    }
+
+    impl Operator for Counted<'_> {
+        fn next(&mut self) -> Result<Option<Vec<Literal>>> {
+            self.pulled.set(self.pulled.get() + 1);
+            self.input.next()
+        }
+
+        fn rewind(&mut self) -> Result<()> {
+            self.input.rewind()
+        }
+    }
+
+    #[test]
+    fn limit_stops_pulling_rows() {
+        let pulled = Rc::new(Cell::new(0));
+        let input = Box::new(Counted { input: values(keyed_rows()), pulled: pulled.clone() });
+        let mut limit = Limit::new(input, Some(3), 10);
+        assert_eq!(collect(&mut limit), ["null|10", "b|11", "a|12"]);
+        assert_eq!(pulled.get(), 13);
+        limit.rewind().unwrap();
+        assert_eq!(collect(&mut limit).len(), 3);
+        assert_eq!(pulled.get(), 26);
+    }
+}
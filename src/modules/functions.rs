@@ -0,0 +1,828 @@
+use std::{
+    cmp::Ordering,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::{Range, RangeInclusive},
+    sync::{OnceLock, atomic::{AtomicU64, Ordering as AtomicOrdering}},
+};
+
+use anyhow::{Result, bail};
+
+use crate::modules::{
+    ast::{Literal, format_real},
+    eval::{Collation, integer, number, text},
+    header::TextEncoding,
+    helpers::encode_text,
+};
+
+/// A built-in scalar function, called once per row with the values of its arguments.
+pub struct ScalarFunction {
+    pub name: &'static str,
+    /// Numbers of arguments it accepts
+    pub arity: RangeInclusive<usize>,
+    body: fn(&[Literal], &Context) -> Result<Literal>,
+}
+
+/// What a function call may need besides the argument values.
+pub(crate) struct Context {
+    pub encoding: TextEncoding,
+    /// Collation of the first argument that has one, for the functions that compare values
+    pub collation: Collation,
+}
+
+/// The scalar functions of SQLite's core library that are supported
+/// (https://www.sqlite.org/lang_corefunc.html).
+static FUNCTIONS: &[ScalarFunction] = &[
+    ScalarFunction { name: "abs", arity: 1..=1, body: abs },
+    ScalarFunction { name: "coalesce", arity: 2..=usize::MAX, body: coalesce },
+    ScalarFunction { name: "format", arity: 0..=usize::MAX, body: printf },
+    ScalarFunction { name: "hex", arity: 1..=1, body: hex },
+    ScalarFunction { name: "ifnull", arity: 2..=2, body: coalesce },
+    ScalarFunction { name: "instr", arity: 2..=2, body: instr },
+    ScalarFunction { name: "length", arity: 1..=1, body: length },
+    ScalarFunction { name: "lower", arity: 1..=1, body: lower },
+    ScalarFunction { name: "ltrim", arity: 1..=2, body: ltrim },
+    // with a single argument these are the aggregates
+    ScalarFunction { name: "max", arity: 2..=usize::MAX, body: max },
+    ScalarFunction { name: "min", arity: 2..=usize::MAX, body: min },
+    ScalarFunction { name: "nullif", arity: 2..=2, body: nullif },
+    ScalarFunction { name: "printf", arity: 0..=usize::MAX, body: printf },
+    ScalarFunction { name: "quote", arity: 1..=1, body: quote },
+    ScalarFunction { name: "random", arity: 0..=0, body: random },
+    ScalarFunction { name: "replace", arity: 3..=3, body: replace },
+    ScalarFunction { name: "round", arity: 1..=2, body: round },
+    ScalarFunction { name: "rtrim", arity: 1..=2, body: rtrim },
+    ScalarFunction { name: "substr", arity: 2..=3, body: substr },
+    ScalarFunction { name: "substring", arity: 2..=3, body: substr },
+    ScalarFunction { name: "trim", arity: 1..=2, body: trim },
+    ScalarFunction { name: "typeof", arity: 1..=1, body: type_of },
+    ScalarFunction { name: "upper", arity: 1..=1, body: upper },
+];
+
+impl ScalarFunction {
+    /// The function called `name`, in any case.
+    pub fn lookup(name: &str) -> Option<&'static Self> {
+        FUNCTIONS.iter().find(|function| function.name.eq_ignore_ascii_case(name))
+    }
+
+    pub(crate) fn call(&self, args: &[Literal], context: &Context) -> Result<Literal> {
+        (self.body)(args, context)
+    }
+}
+
+fn is_null(value: &Literal) -> bool {
+    matches!(value, Literal::Null)
+}
+
+/// The real a value converts to, text counting as the number it starts with.
+fn real(value: &Literal) -> f64 {
+    match number(value) {
+        Literal::IntegerLiteral(n) => n as f64,
+        Literal::RealLiteral(n) => n,
+        _ => 0.0,
+    }
+}
+
+fn abs(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(match &args[0] {
+        Literal::Null => Literal::Null,
+        Literal::IntegerLiteral(i64::MIN) => bail!("integer overflow"),
+        Literal::IntegerLiteral(n) => Literal::IntegerLiteral(n.abs()),
+        value => Literal::RealLiteral(real(value).abs()),
+    })
+}
+
+/// coalesce() and ifnull(): the first argument that is not NULL.
+fn coalesce(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(args.iter().find(|arg| !is_null(arg)).cloned().unwrap_or(Literal::Null))
+}
+
+/// Upper case hexadecimal of a blob, or of text in the database encoding.
+fn hex(args: &[Literal], context: &Context) -> Result<Literal> {
+    let bytes = match &args[0] {
+        Literal::Null => vec![],
+        Literal::BlobLiteral(bytes) => bytes.clone(),
+        Literal::StringLiteral(s) | Literal::Ident(s) => encode_text(s, context.encoding),
+        // numbers become UTF-8 text whatever the encoding
+        value => text(value).into_bytes(),
+    };
+    Ok(Literal::StringLiteral(bytes.iter().map(|byte| format!("{:02X}", byte)).collect()))
+}
+
+/// 1-based position of the first occurrence of the second argument in the first, 0 if there is
+/// none. Counted in bytes for two blobs, else in characters.
+fn instr(args: &[Literal], _: &Context) -> Result<Literal> {
+    let position = match (&args[0], &args[1]) {
+        (Literal::Null, _) | (_, Literal::Null) => return Ok(Literal::Null),
+        (Literal::BlobLiteral(haystack), Literal::BlobLiteral(needle)) => match needle.len() {
+            0 => Some(0),
+            len => haystack.windows(len).position(|window| window == needle.as_slice()),
+        },
+        (haystack, needle) => {
+            let haystack = text(haystack);
+            haystack.find(&text(needle)).map(|byte| haystack[..byte].chars().count())
+        },
+    };
+    Ok(Literal::IntegerLiteral(position.map_or(0, |position| position as i64 + 1)))
+}
+
+/// Characters of text up to the first NUL, bytes of a blob.
+fn length(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(match &args[0] {
+        Literal::Null => Literal::Null,
+        Literal::BlobLiteral(bytes) => Literal::IntegerLiteral(bytes.len() as i64),
+        value => Literal::IntegerLiteral(text(value).chars().take_while(|&c| c != '\0').count() as i64),
+    })
+}
+
+/// upper() and lower() only change ASCII letters, like SQLite without ICU.
+fn upper(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(match &args[0] {
+        Literal::Null => Literal::Null,
+        value => Literal::StringLiteral(text(value).to_ascii_uppercase()),
+    })
+}
+
+fn lower(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(match &args[0] {
+        Literal::Null => Literal::Null,
+        value => Literal::StringLiteral(text(value).to_ascii_lowercase()),
+    })
+}
+
+fn trim(args: &[Literal], _: &Context) -> Result<Literal> {
+    trim_ends(args, true, true)
+}
+
+fn ltrim(args: &[Literal], _: &Context) -> Result<Literal> {
+    trim_ends(args, true, false)
+}
+
+fn rtrim(args: &[Literal], _: &Context) -> Result<Literal> {
+    trim_ends(args, false, true)
+}
+
+/// Removes the characters of the second argument, spaces by default, from the chosen ends.
+fn trim_ends(args: &[Literal], start: bool, end: bool) -> Result<Literal> {
+    if args.iter().any(is_null) {
+        return Ok(Literal::Null);
+    }
+    let characters = args.get(1).map_or(vec![' '], |characters| text(characters).chars().collect());
+    let mut trimmed = text(&args[0]);
+    if end {
+        trimmed.truncate(trimmed.trim_end_matches(characters.as_slice()).len());
+    }
+    if start {
+        trimmed = trimmed.trim_start_matches(characters.as_slice()).to_string();
+    }
+    Ok(Literal::StringLiteral(trimmed))
+}
+
+/// The smallest or largest argument, NULL if any is. Ties go to the last smallest and the first largest.
+fn min(args: &[Literal], context: &Context) -> Result<Literal> {
+    extreme(args, context, |best| best != Ordering::Less)
+}
+
+fn max(args: &[Literal], context: &Context) -> Result<Literal> {
+    extreme(args, context, |best| best == Ordering::Less)
+}
+
+/// The argument picked by comparing each one in turn with the best so far, where `replace` tells
+/// from the ordering of the best against the next whether the next takes its place.
+fn extreme(args: &[Literal], context: &Context, replace: fn(Ordering) -> bool) -> Result<Literal> {
+    if args.iter().any(is_null) {
+        return Ok(Literal::Null);
+    }
+    let mut best = &args[0];
+    for arg in &args[1..] {
+        let ordering = context.collation.compare(best, arg, context.encoding).unwrap_or(Ordering::Equal);
+        if replace(ordering) {
+            best = arg;
+        }
+    }
+    Ok(best.clone())
+}
+
+/// The first argument, or NULL when it equals the second.
+fn nullif(args: &[Literal], context: &Context) -> Result<Literal> {
+    let equal = !is_null(&args[1]) && context.collation.compare(&args[0], &args[1], context.encoding) == Some(Ordering::Equal);
+    Ok(if equal { Literal::Null } else { args[0].clone() })
+}
+
+/// The value as an SQL literal.
+fn quote(args: &[Literal], _: &Context) -> Result<Literal> {
+    Ok(Literal::StringLiteral(match &args[0] {
+        Literal::Null => "NULL".to_string(),
+        Literal::IntegerLiteral(n) => n.to_string(),
+        Literal::RealLiteral(n) if n.is_infinite() => if *n > 0.0 { "9.0e+999" } else { "-9.0e+999" }.to_string(),
+        // 15 significant digits when they read back as the same real, else 20
+        Literal::RealLiteral(n) => match format_real(*n) {
+            short if short.parse() == Ok(*n) => short,
+            _ => format_float(*n, 'e', 20, &Spec { bang: true, ..Spec::default() }),
+        },
+        Literal::StringLiteral(s) | Literal::Ident(s) => format!("'{}'", s.replace('\'', "''")),
+        Literal::BlobLiteral(bytes) => format!("X'{}'", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>()),
+    }))
+}
+
+/// A pseudo-random integer anywhere in the 64-bit range.
+fn random(_: &[Literal], _: &Context) -> Result<Literal> {
+    static SEED: OnceLock<u64> = OnceLock::new();
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let seed = *SEED.get_or_init(|| RandomState::new().build_hasher().finish());
+    // SplitMix64 over a counter
+    let mut z = seed.wrapping_add(CALLS.fetch_add(1, AtomicOrdering::Relaxed).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    Ok(Literal::IntegerLiteral((z ^ (z >> 31)) as i64))
+}
+
+/// Replaces every occurrence of the second argument in the first by the third. An empty pattern
+/// leaves the text as it is, even when the replacement is NULL.
+fn replace(args: &[Literal], _: &Context) -> Result<Literal> {
+    if is_null(&args[0]) || is_null(&args[1]) {
+        return Ok(Literal::Null);
+    }
+    let (value, pattern) = (text(&args[0]), text(&args[1]));
+    if pattern.is_empty() {
+        return Ok(Literal::StringLiteral(value));
+    }
+    if is_null(&args[2]) {
+        return Ok(Literal::Null);
+    }
+    Ok(Literal::StringLiteral(value.replace(&pattern, &text(&args[2]))))
+}
+
+/// Rounds to a number of digits after the point, between 0 and 30, always giving a real.
+fn round(args: &[Literal], _: &Context) -> Result<Literal> {
+    if args.iter().any(is_null) {
+        return Ok(Literal::Null);
+    }
+    let digits = args.get(1).map_or(0, integer).clamp(0, 30);
+    let n = real(&args[0]);
+    let rounded = if digits == 0 && n.abs() < 9223372036854775806.0 {
+        // half away from zero
+        let whole = (n.abs() + 0.5) as i64 as f64;
+        if n < 0.0 { -whole } else { whole }
+    } else {
+        format_float(n, 'f', digits as usize, &Spec { bang: true, ..Spec::default() }).parse().unwrap_or(n)
+    };
+    Ok(Literal::RealLiteral(rounded))
+}
+
+/// Characters of text, or bytes of a blob, from a 1-based start counted from the end when
+/// negative, for a length that takes the characters before the start when negative.
+fn substr(args: &[Literal], _: &Context) -> Result<Literal> {
+    if args.iter().any(is_null) {
+        return Ok(Literal::Null);
+    }
+    let start = integer(&args[1]);
+    let length = args.get(2).map(integer);
+    Ok(match &args[0] {
+        Literal::BlobLiteral(bytes) => Literal::BlobLiteral(bytes[substr_range(bytes.len(), start, length)].to_vec()),
+        value => {
+            let chars = text(value).chars().collect::<Vec<_>>();
+            Literal::StringLiteral(chars[substr_range(chars.len(), start, length)].iter().collect())
+        },
+    })
+}
+
+/// The part of a value of `len` characters or bytes that substr() takes.
+fn substr_range(len: usize, start: i64, length: Option<i64>) -> Range<usize> {
+    let len = len as i64;
+    let (mut start, mut length, backwards) = match length {
+        Some(length) => (start, length.saturating_abs(), length < 0),
+        None => (start, i64::MAX, false),
+    };
+    if start < 0 {
+        start += len;
+        if start < 0 {
+            length = (length + start).max(0);
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if length > 0 {
+        // position 0 is just before the first character
+        length -= 1;
+    }
+    if backwards {
+        start -= length;
+        if start < 0 {
+            length += start;
+            start = 0;
+        }
+    }
+    let begin = start.min(len);
+    begin as usize..start.saturating_add(length).clamp(begin, len) as usize
+}
+
+fn type_of(args: &[Literal], _: &Context) -> Result<Literal> {
+    let name = match &args[0] {
+        Literal::Null => "null",
+        Literal::IntegerLiteral(_) => "integer",
+        Literal::RealLiteral(_) => "real",
+        Literal::StringLiteral(_) | Literal::Ident(_) => "text",
+        Literal::BlobLiteral(_) => "blob",
+    };
+    Ok(Literal::StringLiteral(name.to_string()))
+}
+
+/// printf() and format(): the format string with each `%` conversion replaced by the next
+/// argument, following SQLite's printf (https://www.sqlite.org/printf.html). Missing arguments
+/// count as NULL, and an unknown conversion ends the output. Without a format the result is NULL.
+fn printf(args: &[Literal], _: &Context) -> Result<Literal> {
+    let Some(format) = args.first().filter(|format| !is_null(format)).map(text) else {
+        return Ok(Literal::Null);
+    };
+    let mut args = args[1..].iter();
+    let mut next_arg = || args.next().unwrap_or(&Literal::Null);
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.sign = Some('+'),
+                ' ' => spec.sign = spec.sign.or(Some(' ')),
+                '#' => spec.alternate = true,
+                '!' => spec.bang = true,
+                '0' => spec.zero = true,
+                ',' => spec.thousands = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        // like SQLite, a width or precision taken from an argument is a 32-bit int
+        if chars.next_if_eq(&'*').is_some() {
+            let width = integer(next_arg()) as i32;
+            spec.left |= width < 0;
+            spec.width = width.checked_abs().unwrap_or(0) as usize;
+        } else {
+            spec.width = digits(&mut chars);
+        }
+        if chars.next_if_eq(&'.').is_some() {
+            spec.precision = if chars.next_if_eq(&'*').is_some() {
+                (integer(next_arg()) as i32).checked_abs().map(|precision| precision as usize)
+            } else {
+                Some(digits(&mut chars))
+            };
+        }
+        while chars.next_if_eq(&'l').is_some() {}
+        let Some(conversion) = chars.next() else {
+            break;
+        };
+        // the width, and the precision of integers and %c, are lengths the output reaches
+        let integral = matches!(conversion, 'd' | 'i' | 'u' | 'x' | 'X' | 'o' | 'c');
+        if spec.width > MAX_LENGTH || (integral && spec.precision.is_some_and(|precision| precision > MAX_LENGTH)) {
+            bail!("string or blob too big");
+        }
+        let body = match conversion {
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => format_integer(integer(next_arg()), conversion, &spec),
+            'f' | 'e' | 'E' | 'g' | 'G' => {
+                format_float(real(next_arg()), conversion, spec.float_precision(), &spec)
+            },
+            's' | 'z' => match next_arg() {
+                Literal::Null => String::new(),
+                value => spec.truncate(text(value)),
+            },
+            'c' => {
+                let c = text(next_arg()).chars().next().map_or(String::new(), String::from);
+                let count = spec.precision.unwrap_or(1).max(1);
+                if count.saturating_mul(c.len()) > MAX_LENGTH {
+                    bail!("string or blob too big");
+                }
+                c.repeat(count)
+            },
+            'q' | 'Q' | 'w' => match next_arg() {
+                Literal::Null if conversion == 'Q' => "NULL".to_string(),
+                Literal::Null => "(NULL)".to_string(),
+                value => {
+                    let quote = if conversion == 'w' { "\"" } else { "'" };
+                    let escaped = spec.truncate(text(value)).replace(quote, &quote.repeat(2));
+                    if conversion == 'Q' { format!("'{}'", escaped) } else { escaped }
+                },
+            },
+            '%' => "%".to_string(),
+            'n' => String::new(),
+            _ => break,
+        };
+        let body = spec.pad(body);
+        if out.len() + body.len() > MAX_LENGTH {
+            bail!("string or blob too big");
+        }
+        out.push_str(&body);
+    }
+    Ok(Literal::StringLiteral(out))
+}
+
+/// SQLite's SQLITE_MAX_LENGTH, the longest string printf() builds.
+const MAX_LENGTH: usize = 1_000_000_000;
+
+/// SQLite's SQLITE_FP_PRECISION_LIMIT, the most digits printf() writes after the point of a real.
+const FLOAT_PRECISION_LIMIT: usize = 100_000_000;
+
+/// Flags, width and precision of a printf conversion.
+#[derive(Default)]
+struct Spec {
+    /// `-`: pad on the right
+    left: bool,
+    /// `+` or ` `: what goes in front of positive numbers
+    sign: Option<char>,
+    /// `#`: prefix hexadecimal and octal, keep the point and zeros of `%g`
+    alternate: bool,
+    /// `!`: more digits for reals, and widths in characters rather than bytes
+    bang: bool,
+    /// `0`: pad numbers with zeros
+    zero: bool,
+    /// `,`: separate thousands
+    thousands: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Digits of a real, 6 unless given and never above [`FLOAT_PRECISION_LIMIT`].
+    fn float_precision(&self) -> usize {
+        self.precision.unwrap_or(6).min(FLOAT_PRECISION_LIMIT)
+    }
+
+    fn len(&self, s: &str) -> usize {
+        if self.bang { s.chars().count() } else { s.len() }
+    }
+
+    /// Text cut to the precision.
+    fn truncate(&self, mut s: String) -> String {
+        if let Some(precision) = self.precision {
+            let end = if self.bang {
+                s.char_indices().nth(precision).map_or(s.len(), |(i, _)| i)
+            } else {
+                (0..=precision.min(s.len())).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+            };
+            s.truncate(end);
+        }
+        s
+    }
+
+    /// Pads with spaces to the width.
+    fn pad(&self, body: String) -> String {
+        let padding = " ".repeat(self.width.saturating_sub(self.len(&body)));
+        if self.left { body + &padding } else { padding + &body }
+    }
+}
+
+/// Number spelled by the digits at the front of `chars`, 0 when there are none. Like SQLite,
+/// it wraps as a 32-bit unsigned int and keeps the low 31 bits.
+fn digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> usize {
+    let mut n = 0u32;
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        n = n.wrapping_mul(10).wrapping_add(digit as u32 - '0' as u32);
+    }
+    (n & 0x7fff_ffff) as usize
+}
+
+/// `%d`, `%u`, `%x`, `%X` or `%o`, where the precision is the least number of digits.
+fn format_integer(value: i64, conversion: char, spec: &Spec) -> String {
+    let signed = matches!(conversion, 'd' | 'i');
+    let magnitude = if signed { value.unsigned_abs() } else { value as u64 };
+    let sign = if signed && value < 0 { Some('-') } else if signed { spec.sign } else { None };
+    let mut digits = match conversion {
+        'x' => format!("{:x}", magnitude),
+        'X' => format!("{:X}", magnitude),
+        'o' => format!("{:o}", magnitude),
+        _ => magnitude.to_string(),
+    };
+    let mut precision = spec.precision.unwrap_or(0);
+    if spec.zero {
+        precision = precision.max(spec.width.saturating_sub(sign.is_some() as usize));
+    }
+    if digits.len() < precision {
+        digits.insert_str(0, &"0".repeat(precision - digits.len()));
+    }
+    if spec.thousands && matches!(conversion, 'd' | 'i' | 'u') {
+        let bytes = digits.as_bytes();
+        let mut grouped = String::new();
+        for (i, &digit) in bytes.iter().enumerate() {
+            if i > 0 && (bytes.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit as char);
+        }
+        digits = grouped;
+    }
+    let prefix = match conversion {
+        _ if !spec.alternate || magnitude == 0 => "",
+        'x' => "0x",
+        'X' => "0X",
+        'o' => "0",
+        _ => "",
+    };
+    format!("{}{}{}", prefix, sign.map_or(String::new(), String::from), digits)
+}
+
+/// `%f`, `%e`, `%E`, `%g` or `%G` of a real.
+fn format_float(value: f64, conversion: char, precision: usize, spec: &Spec) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        let sign = if value < 0.0 { Some('-') } else { spec.sign };
+        return format!("{}Inf", sign.map_or(String::new(), String::from));
+    }
+    let mut precision = precision as i64;
+    let generic = matches!(conversion, 'g' | 'G');
+    if generic {
+        precision = precision.max(1);
+    }
+    let round = match conversion {
+        'f' => -precision,
+        _ if generic => precision,
+        _ => precision + 1,
+    };
+    let decimal = Decimal::new(value, round, if spec.bang { 26 } else { 16 });
+    let mut out = String::new();
+    if decimal.negative {
+        out.push('-');
+    } else if let Some(sign) = spec.sign {
+        out.push(sign);
+    }
+    let sign_len = out.len();
+    let exponent = decimal.point - 1;
+    let mut exponential = matches!(conversion, 'e' | 'E');
+    let mut strip_zeros = spec.bang;
+    if generic {
+        precision -= 1;
+        strip_zeros = !spec.alternate;
+        if exponent < -4 || exponent > precision {
+            exponential = true;
+        } else {
+            precision -= exponent;
+        }
+    }
+    let mut digits = decimal.digits.iter().map(|&digit| digit as char);
+    let mut next_digit = || digits.next().unwrap_or('0');
+    let mut place = if exponential { 0 } else { exponent };
+    if place < 0 {
+        out.push('0');
+    }
+    while place >= 0 {
+        out.push(next_digit());
+        if spec.thousands && place % 3 == 0 && place > 1 {
+            out.push(',');
+        }
+        place -= 1;
+    }
+    let point = precision > 0 || spec.alternate || spec.bang;
+    if point {
+        out.push('.');
+    }
+    // zeros between the point and the first significant digit
+    place += 1;
+    while place < 0 && precision > 0 {
+        out.push('0');
+        place += 1;
+        precision -= 1;
+    }
+    for _ in 0..precision {
+        out.push(next_digit());
+    }
+    if strip_zeros && point {
+        out.truncate(out.trim_end_matches('0').len());
+        if out.ends_with('.') {
+            if spec.bang { out.push('0') } else { out.pop(); }
+        }
+    }
+    if exponential {
+        out.push(if conversion.is_ascii_uppercase() { 'E' } else { 'e' });
+        out.push(if exponent < 0 { '-' } else { '+' });
+        out.push_str(&format!("{:02}", exponent.abs()));
+    }
+    if spec.zero && !spec.left && out.len() < spec.width {
+        out.insert_str(sign_len, &"0".repeat(spec.width - out.len()));
+    }
+    out
+}
+
+/// Decimal digits of a real, as SQLite's printf takes them: 18 or 19 significant digits from
+/// scaling it with double-double arithmetic, then rounded half up once where the conversion asks.
+struct Decimal {
+    negative: bool,
+    /// ASCII digits without trailing zeros, at least one
+    digits: Vec<u8>,
+    /// Digits before the decimal point, negative when zeros follow the point
+    point: i64,
+}
+
+impl Decimal {
+    /// A positive `round` keeps that many significant digits, else `-round` digits after the point.
+    /// No more than `max_digits` are ever kept.
+    fn new(value: f64, round: i64, max_digits: i64) -> Self {
+        let negative = value < 0.0;
+        if value == 0.0 {
+            return Self { negative, digits: vec![b'0'], point: 1 };
+        }
+        let (mut digits, mut point) = scaled_digits(value.abs());
+        strip_trailing_zeros(&mut digits);
+        let mut round = round;
+        if round <= 0 {
+            round = point - round;
+            // rounding just before the first digit may carry into a new one
+            if round == 0 && digits[0] >= b'5' {
+                digits.insert(0, b'0');
+                point += 1;
+                round = 1;
+            }
+        }
+        if round > 0 && (round < digits.len() as i64 || digits.len() as i64 > max_digits) {
+            let keep = round.min(max_digits) as usize;
+            let up = digits[keep] >= b'5';
+            digits.truncate(keep);
+            if up {
+                match digits.iter().rposition(|&digit| digit != b'9') {
+                    Some(i) => {
+                        digits[i] += 1;
+                        digits.truncate(i + 1);
+                    },
+                    None => {
+                        digits = vec![b'1'];
+                        point += 1;
+                    },
+                }
+            }
+        }
+        strip_trailing_zeros(&mut digits);
+        Self { negative, digits, point }
+    }
+}
+
+/// Digits of a positive real and the position of the point, from scaling it by powers of ten
+/// into the range of a u64 the way SQLite does, so that the last digits agree with sqlite3.
+/// The constants are SQLite's, written as it writes them.
+#[allow(clippy::excessive_precision)]
+fn scaled_digits(value: f64) -> (Vec<u8>, i64) {
+    let mut scaled = [value, 0.0];
+    let mut exponent = 0;
+    if scaled[0] > 9.223372036854774784e+18 {
+        while scaled[0] > 9.223372036854774784e+118 {
+            exponent += 100;
+            dekker_multiply(&mut scaled, 1.0e-100, -1.99918998026028836196e-117);
+        }
+        while scaled[0] > 9.223372036854774784e+28 {
+            exponent += 10;
+            dekker_multiply(&mut scaled, 1.0e-10, -3.6432197315497741579e-27);
+        }
+        while scaled[0] > 9.223372036854774784e+18 {
+            exponent += 1;
+            dekker_multiply(&mut scaled, 1.0e-01, -5.5511151231257827021e-18);
+        }
+    } else {
+        while scaled[0] < 9.223372036854774784e-83 {
+            exponent -= 100;
+            dekker_multiply(&mut scaled, 1.0e+100, -1.5902891109759918046e+83);
+        }
+        while scaled[0] < 9.223372036854774784e+07 {
+            exponent -= 10;
+            dekker_multiply(&mut scaled, 1.0e+10, 0.0);
+        }
+        while scaled[0] < 9.22337203685477478e+17 {
+            exponent -= 1;
+            dekker_multiply(&mut scaled, 1.0e+01, 0.0);
+        }
+    }
+    let [high, low] = scaled;
+    let integer = if low < 0.0 { (high as u64).wrapping_sub(-low as u64) } else { (high as u64).wrapping_add(low as u64) };
+    let digits = integer.to_string().into_bytes();
+    let point = digits.len() as i64 + exponent;
+    (digits, point)
+}
+
+/// Multiplies the double-double `x` by `y + yy`, where `yy` is the rounding error of the constant `y`.
+fn dekker_multiply(x: &mut [f64; 2], y: f64, yy: f64) {
+    // upper halves of the mantissas, whose products are exact
+    let split = |n: f64| f64::from_bits(n.to_bits() & 0xffff_ffff_fc00_0000);
+    let (hx, hy) = (split(x[0]), split(y));
+    let (tx, ty) = (x[0] - hx, y - hy);
+    let p = hx * hy;
+    let q = hx * ty + tx * hy;
+    let c = p + q;
+    let cc = x[0] * yy + x[1] * y + (p - c + q + tx * ty);
+    x[0] = c + cc;
+    x[1] = c - x[0] + cc;
+}
+
+fn strip_trailing_zeros(digits: &mut Vec<u8>) {
+    while digits.len() > 1 && digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf_of(args: &[Literal]) -> Result<Literal> {
+        let context = Context { encoding: TextEncoding::Utf8, collation: Collation::Binary };
+        printf(args, &context)
+    }
+
+    fn formatted(args: &[Literal]) -> String {
+        match printf_of(args).unwrap() {
+            Literal::StringLiteral(s) => s,
+            other => panic!("printf() gave {:?}", other),
+        }
+    }
+
+    fn string(s: &str) -> Literal {
+        Literal::StringLiteral(s.to_string())
+    }
+
+    #[test]
+    fn star_arguments_are_32_bit() {
+        // i64::MAX is -1 as an int: left justified to a width of 1
+        assert_eq!(formatted(&[string("%*d|"), Literal::IntegerLiteral(i64::MAX), Literal::IntegerLiteral(1)]), "1|");
+        assert_eq!(formatted(&[string("%.*c"), Literal::IntegerLiteral(i64::MAX), string("a")]), "a");
+        assert_eq!(formatted(&[string("%*d"), Literal::IntegerLiteral(4294967301), Literal::IntegerLiteral(1)]), "    1");
+        assert_eq!(formatted(&[string("%.*d"), Literal::IntegerLiteral(4294967297), Literal::IntegerLiteral(7)]), "7");
+        assert_eq!(formatted(&[string("%*d|"), Literal::IntegerLiteral(-3), Literal::IntegerLiteral(7)]), "7  |");
+    }
+
+    #[test]
+    fn float_precision_is_capped() {
+        assert_eq!(Spec::default().float_precision(), 6);
+        assert_eq!(Spec { precision: Some(2_000_000_000), ..Spec::default() }.float_precision(), FLOAT_PRECISION_LIMIT);
+        assert_eq!(formatted(&[string("%.*f"), Literal::IntegerLiteral(3), Literal::RealLiteral(1.0)]), "1.000");
+    }
+
+    #[test]
+    fn lengths_past_the_limit_are_too_big() {
+        for args in [
+            vec![string("%*d"), Literal::IntegerLiteral(2_000_000_000), Literal::IntegerLiteral(1)],
+            vec![string("%.*d"), Literal::IntegerLiteral(2_000_000_000), Literal::IntegerLiteral(1)],
+            vec![string("%.*c"), Literal::IntegerLiteral(1_500_000_000), string("a")],
+            vec![string("%2000000000s"), string("a")],
+        ] {
+            let error = printf_of(&args).unwrap_err();
+            assert_eq!(error.to_string(), "string or blob too big");
+        }
+    }
+
+    #[test]
+    fn width_digits_wrap_like_sqlite() {
+        // 4294967297 wraps to 1 as a 32-bit unsigned int
+        assert_eq!(formatted(&[string("%4294967297d"), Literal::IntegerLiteral(5)]), "5");
+    }
+
+    #[test]
+    fn float_conversions() {
+        let spec = Spec::default();
+        assert_eq!(format_float(2.71, 'f', 3, &spec), "2.710");
+        assert_eq!(format_float(0.5, 'f', 0, &spec), "1");
+        // the nearest double to 9.995 is below it
+        assert_eq!(format_float(9.995, 'f', 2, &spec), "9.99");
+        assert_eq!(format_float(1234.5, 'e', 3, &spec), "1.235e+03");
+        assert_eq!(format_float(0.0001, 'g', 6, &spec), "0.0001");
+        assert_eq!(format_float(0.00001, 'g', 6, &spec), "1e-05");
+        assert_eq!(format_float(100000.0, 'g', 6, &spec), "100000");
+        assert_eq!(format_float(1e6, 'G', 6, &spec), "1E+06");
+        assert_eq!(format_float(-2.5, 'f', 1, &spec), "-2.5");
+        assert_eq!(format_float(f64::INFINITY, 'f', 6, &spec), "Inf");
+        let spec = Spec { thousands: true, ..Spec::default() };
+        assert_eq!(format_float(1234567.891, 'f', 2, &spec), "1,234,567.89");
+        let spec = Spec { zero: true, width: 8, sign: Some('+'), ..Spec::default() };
+        assert_eq!(format_float(3.5, 'f', 1, &spec), "+00003.5");
+    }
+
+    #[test]
+    fn decimal_digits() {
+        let decimal = Decimal::new(0.1, 17, 16);
+        assert_eq!((decimal.digits.as_slice(), decimal.point), (&b"1"[..], 0));
+        let decimal = Decimal::new(123.456, -2, 16);
+        assert_eq!((decimal.digits.as_slice(), decimal.point), (&b"12346"[..], 3));
+        let decimal = Decimal::new(-0.0042, 1, 16);
+        assert!(decimal.negative);
+        assert_eq!((decimal.digits.as_slice(), decimal.point), (&b"4"[..], -2));
+        // rounding just before the first digit carries into a new one
+        let decimal = Decimal::new(0.6, 0, 16);
+        assert_eq!((decimal.digits.as_slice(), decimal.point), (&b"1"[..], 1));
+        let decimal = Decimal::new(99.97, 3, 16);
+        assert_eq!((decimal.digits.as_slice(), decimal.point), (&b"1"[..], 3));
+    }
+
+    #[test]
+    fn substr_ranges() {
+        assert_eq!(substr_range(5, 2, Some(3)), 1..4);
+        assert_eq!(substr_range(5, 2, None), 1..5);
+        assert_eq!(substr_range(5, 0, Some(2)), 0..1);
+        assert_eq!(substr_range(5, -2, None), 3..5);
+        assert_eq!(substr_range(5, -7, Some(3)), 0..1);
+        assert_eq!(substr_range(5, 4, Some(-2)), 1..3);
+        assert_eq!(substr_range(5, 2, Some(-3)), 0..1);
+        assert_eq!(substr_range(5, 9, Some(2)), 5..5);
+        assert_eq!(substr_range(5, 1, Some(i64::MIN)), 0..0);
+    }
+}
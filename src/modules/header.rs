@@ -0,0 +1,180 @@
+use anyhow::{Result, bail};
+
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16le,
+    Utf16be,
+}
+
+impl TextEncoding {
+    fn code(&self) -> u32 {
+        match self {
+            Self::Utf8 => 1,
+            Self::Utf16le => 2,
+            Self::Utf16be => 3,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf8",
+            Self::Utf16le => "utf16le",
+            Self::Utf16be => "utf16be",
+        }
+    }
+}
+
+/// The 100 byte header at the start of the database file, see https://www.sqlite.org/fileformat.html#the_database_header
+#[derive(Debug, Clone)]
+pub struct DatabaseHeader {
+    pub page_size: u32,
+    pub write_version: u8,
+    pub read_version: u8,
+    pub reserved_bytes: u8,
+    pub file_change_counter: u32,
+    pub database_size: u32,
+    pub freelist_trunk: u32,
+    pub freelist_count: u32,
+    pub schema_cookie: u32,
+    pub schema_format: u32,
+    pub default_cache_size: u32,
+    pub autovacuum_top_root: u32,
+    pub text_encoding: TextEncoding,
+    pub user_version: u32,
+    pub incremental_vacuum: u32,
+    pub application_id: u32,
+    pub sqlite_version: u32,
+}
+
+fn get_u32(header: &[u8; 100], offset: usize) -> u32 {
+    u32::from_be_bytes([header[offset], header[offset+1], header[offset+2], header[offset+3]])
+}
+
+impl DatabaseHeader {
+    pub fn parse(header: &[u8; 100]) -> Result<Self> {
+        if &header[..16] != MAGIC {
+            bail!("File is not a database: bad header magic")
+        }
+        // The page size is stored at the 16th byte offset, using 2 bytes in big-endian order, 1 means 65536
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            n => n as u32,
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            bail!("Invalid page size: {}", page_size)
+        }
+        // the payload fractions are fixed by the file format
+        if header[21..24] != [64, 32, 32] {
+            bail!("Invalid payload fractions: {:?}", &header[21..24])
+        }
+        let text_encoding = match get_u32(header, 56) {
+            1 => TextEncoding::Utf8,
+            2 => TextEncoding::Utf16le,
+            3 => TextEncoding::Utf16be,
+            n => bail!("Invalid text encoding: {}", n),
+        };
+        Ok(Self {
+            page_size,
+            write_version: header[18],
+            read_version: header[19],
+            reserved_bytes: header[20],
+            file_change_counter: get_u32(header, 24),
+            database_size: get_u32(header, 28),
+            freelist_trunk: get_u32(header, 32),
+            freelist_count: get_u32(header, 36),
+            schema_cookie: get_u32(header, 40),
+            schema_format: get_u32(header, 44),
+            default_cache_size: get_u32(header, 48),
+            autovacuum_top_root: get_u32(header, 52),
+            text_encoding,
+            user_version: get_u32(header, 60),
+            incremental_vacuum: get_u32(header, 64),
+            application_id: get_u32(header, 68),
+            sqlite_version: get_u32(header, 96),
+        })
+    }
+
+    /// Header part of the `.dbinfo` report, in the same order and layout as the sqlite3 shell.
+    pub fn dbinfo_fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("database page size:", self.page_size.to_string()),
+            ("write format:", self.write_version.to_string()),
+            ("read format:", self.read_version.to_string()),
+            ("reserved bytes:", self.reserved_bytes.to_string()),
+            ("file change counter:", self.file_change_counter.to_string()),
+            ("database page count:", self.database_size.to_string()),
+            ("freelist page count:", self.freelist_count.to_string()),
+            ("schema cookie:", self.schema_cookie.to_string()),
+            ("schema format:", self.schema_format.to_string()),
+            ("default cache size:", self.default_cache_size.to_string()),
+            ("autovacuum top root:", self.autovacuum_top_root.to_string()),
+            ("incremental vacuum:", self.incremental_vacuum.to_string()),
+            ("text encoding:", format!("{} ({})", self.text_encoding.code(), self.text_encoding.name())),
+            ("user version:", self.user_version.to_string()),
+            ("application id:", self.application_id.to_string()),
+            ("software version:", self.sqlite_version.to_string()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(page_size: u16, text_encoding: u32) -> [u8; 100] {
+        let mut header = [0; 100];
+        header[..16].copy_from_slice(MAGIC);
+        header[16..18].copy_from_slice(&page_size.to_be_bytes());
+        header[18] = 1;
+        header[19] = 1;
+        header[20] = 8;
+        header[21..24].copy_from_slice(&[64, 32, 32]);
+        header[24..28].copy_from_slice(&7u32.to_be_bytes());
+        header[28..32].copy_from_slice(&3u32.to_be_bytes());
+        header[56..60].copy_from_slice(&text_encoding.to_be_bytes());
+        header[96..100].copy_from_slice(&3051002u32.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn parses_fields() {
+        let parsed = DatabaseHeader::parse(&header(4096, 2)).unwrap();
+        assert_eq!(parsed.page_size, 4096);
+        assert_eq!(parsed.reserved_bytes, 8);
+        assert_eq!(parsed.file_change_counter, 7);
+        assert_eq!(parsed.database_size, 3);
+        assert_eq!(parsed.text_encoding, TextEncoding::Utf16le);
+        assert_eq!(parsed.sqlite_version, 3051002);
+    }
+
+    #[test]
+    fn page_size_one_means_65536() {
+        assert_eq!(DatabaseHeader::parse(&header(1, 1)).unwrap().page_size, 65536);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bad_magic = header(4096, 1);
+        bad_magic[..6].copy_from_slice(b"MySQL ");
+        assert!(DatabaseHeader::parse(&bad_magic).unwrap_err().to_string().contains("bad header magic"));
+        assert!(DatabaseHeader::parse(&header(1000, 1)).is_err());
+        assert!(DatabaseHeader::parse(&header(256, 1)).is_err());
+        assert!(DatabaseHeader::parse(&header(4096, 4)).is_err());
+        let mut bad_fractions = header(4096, 1);
+        bad_fractions[21] = 65;
+        assert!(DatabaseHeader::parse(&bad_fractions).is_err());
+    }
+
+    #[test]
+    fn dbinfo_layout() {
+        let fields = DatabaseHeader::parse(&header(512, 3)).unwrap().dbinfo_fields();
+        let lines = fields.iter().map(|(name, value)| format!("{:<20} {}", name, value)).collect::<Vec<_>>();
+        assert_eq!(lines[0], "database page size:  512");
+        assert_eq!(lines[3], "reserved bytes:      8");
+        assert_eq!(lines[12], "text encoding:       3 (utf16be)");
+        assert_eq!(lines[15], "software version:    3051002");
+    }
+}
@@ -1,8 +1,8 @@
-use std::{fs::File, os::unix::fs::FileExt};
+use std::cmp::Ordering;
 
-use anyhow::{Result, anyhow, bail};
+use anyhow::{Result, bail};
 
-use crate::modules::{ast::{Expr, Literal}, table::{Column, Table}};
+use crate::modules::{ast::Literal, header::TextEncoding, pager::Pager};
 
 fn get_column_size(ctype: i64) -> usize {
     if ctype < 12 {
@@ -34,41 +34,23 @@ fn get_column_type(ctype: i64) -> u8 {
 
 fn get_u64_from_size_n(buff: &[u8], n: usize) -> u64 {
     let mut my_num = 0;
-    for i in 0..n {
+    for byte in &buff[..n] {
         my_num <<= 8;
-        my_num |= buff[i] as u64;
+        my_num |= *byte as u64;
     }
     my_num
 }
 
-fn get_num_size_n(buff: &[u8], n: usize, is_float: bool) -> f64 {
-    if is_float {
-        let mut hull = 0u64;
-        for i in 0..n {
-            let my_byte = buff[i];
-            hull <<= 8;
-            hull |= my_byte as u64;
-        }
-        f64::from_bits(hull)
-    } else {
-        if n == 1 {
-            buff[0] as f64
-        } else if n == 2 {
-            let my_num = i16::from_be_bytes([buff[0], buff[1]]);
-            my_num as f64
-        } else if n == 4 {
-            let my_num = i32::from_be_bytes([buff[0], buff[1], buff[2], buff[3]]);
-            my_num as f64
-        } else if n == 8 {
-            let my_num = i64::from_be_bytes([buff[0], buff[1], buff[2], buff[3], buff[4], buff[5], buff[6], buff[7]]);
-            my_num as f64
-        } else {
-            0.0
-        }
+/// Reads an `n` byte big-endian two's complement integer.
+fn get_int_size_n(buff: &[u8], n: usize) -> i64 {
+    if n == 0 {
+        return 0;
     }
+    let shift = 64 - 8 * n as u32;
+    ((get_u64_from_size_n(buff, n) << shift) as i64) >> shift
 }
 
-fn get_varint(data: &[u8], current_offset: &mut usize) -> i64 {
+pub fn get_varint(data: &[u8], current_offset: &mut usize) -> i64 {
     let mut out: i64 = 0;
     let mut byte_num = 0;
     for byte in &data[*current_offset..] {
@@ -90,306 +72,384 @@ fn get_varint(data: &[u8], current_offset: &mut usize) -> i64 {
     out
 }
 
-pub fn get_table_info(buffer: &[u8]) -> (Vec<Table>, Vec<Table>) {
-    let table_num = u16::from_be_bytes([buffer[100+3], buffer[100+4]]);
-    let mut tables = vec![];
-    let mut indices = vec![];
-    for i in 0..table_num as usize {
-        let mut current_offset = u16::from_be_bytes([buffer[100+8+2*i], buffer[100+8+2*i+1]]) as usize;
-        get_varint(&buffer, &mut current_offset); // size of record
-        get_varint(&buffer, &mut current_offset); // the rowid
-        get_varint(&buffer, &mut current_offset); // record header size
-        let schema_type_size = (get_varint(&buffer, &mut current_offset)as usize-13)/2; // sqlite_schema.type
-        let schema_name_size = (get_varint(&buffer, &mut current_offset)as usize-13)/2; // sqlite_schema.name
-        let tbl_name_size = (get_varint(&buffer, &mut current_offset)as usize-13)/2; // sqlite_schema.tbl_name
-        let rootpage_size = get_varint(&buffer, &mut current_offset); // sqlite_schema.rootpage
-        let sql_size = (get_varint(&buffer, &mut current_offset)as usize-13)/2; // sqlite_schema.sql
-
-        // start record payload
-        let schema_type = String::from_utf8(buffer[current_offset..current_offset+schema_type_size].to_vec()).unwrap();
-        current_offset += schema_type_size + schema_name_size;
-        let tbl_name = buffer[current_offset..current_offset+tbl_name_size].to_vec();
-        let tbl_name_string = String::from_utf8(tbl_name).expect("table name is not a string");
-        if tbl_name_string == "sqlite_sequence" {
-            continue;
+/// Appends `value` as a big-endian varint of 1 to 9 bytes, the inverse of [`get_varint`].
+pub fn put_varint(out: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        // the ninth byte carries a full 8 bits
+        let mut bytes = [0u8; 9];
+        bytes[8] = value as u8;
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
         }
-        current_offset += tbl_name_size;
-        let rootpage = get_u64_from_size_n(&buffer[current_offset..], rootpage_size as usize) as u32;
-        current_offset += rootpage_size as usize;
-        let sql = buffer[current_offset..current_offset+sql_size].to_vec();
-        let sql_string = String::from_utf8(sql).expect("table sql is not a string");
-        let parameters = sql_string.split('(').nth(1).unwrap().strip_suffix(')').unwrap();
+        out.extend_from_slice(&bytes);
+        return;
+    }
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest != 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
 
-        let mut columns = vec![];
-        for parameter in parameters.split(',') {
-            let parameter = parameter.trim().split(" ").collect::<Vec<_>>();
-            let parameter_name = parameter[0];
-            let parameter_type;
-            if schema_type == "table" {
-                parameter_type = parameter[1];
-            } else {
-                parameter_type = "";
-            }
-            let column = Column::new(parameter_name, parameter_type);
-            columns.push(column);
+/// Which kind of b-tree cell a payload belongs to, since the overflow thresholds differ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellKind {
+    TableLeaf,
+    Index,
+}
+
+/// Number of payload bytes stored on the b-tree page itself, the rest spills to overflow pages.
+fn get_local_payload_size(payload_size: usize, usable_size: usize, kind: CellKind) -> usize {
+    let max_local = match kind {
+        CellKind::TableLeaf => usable_size - 35,
+        CellKind::Index => (usable_size - 12) * 64 / 255 - 23,
+    };
+    if payload_size <= max_local {
+        return payload_size;
+    }
+    let min_local = (usable_size - 12) * 32 / 255 - 23;
+    let local = min_local + (payload_size - min_local) % (usable_size - 4);
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Reads the full payload of the cell starting at `offset`, following the overflow chain if needed.
+pub fn read_payload(pager: &Pager, buffer: &[u8], offset: usize, payload_size: usize, kind: CellKind) -> Result<Vec<u8>> {
+    let local_size = get_local_payload_size(payload_size, pager.usable_size, kind);
+    let mut payload = buffer[offset..offset+local_size].to_vec();
+    if local_size == payload_size {
+        return Ok(payload);
+    }
+    let pointer = offset + local_size;
+    let mut overflow_page = u32::from_be_bytes([buffer[pointer], buffer[pointer+1], buffer[pointer+2], buffer[pointer+3]]);
+    while payload.len() < payload_size {
+        if overflow_page == 0 {
+            bail!("Overflow chain ended with {} of {} payload bytes read", payload.len(), payload_size)
         }
+        let overflow_buffer = pager.read_page(overflow_page)?;
+        overflow_page = u32::from_be_bytes([overflow_buffer[0], overflow_buffer[1], overflow_buffer[2], overflow_buffer[3]]);
+        let chunk_size = (payload_size - payload.len()).min(pager.usable_size - 4);
+        payload.extend_from_slice(&overflow_buffer[4..4+chunk_size]);
+    }
+    Ok(payload)
+}
 
-        let table = Table::new(&tbl_name_string, rootpage, columns);
+fn decode_text(bytes: &[u8], encoding: TextEncoding) -> Result<String> {
+    let units = bytes.chunks_exact(2);
+    match encoding {
+        TextEncoding::Utf8 => Ok(String::from_utf8(bytes.to_vec())?),
+        TextEncoding::Utf16le => Ok(String::from_utf16(&units.map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>())?),
+        TextEncoding::Utf16be => Ok(String::from_utf16(&units.map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>())?),
+    }
+}
+
+/// Bytes of `text` in the database encoding.
+pub fn encode_text(text: &str, encoding: TextEncoding) -> Vec<u8> {
+    match encoding {
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+        TextEncoding::Utf16le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        TextEncoding::Utf16be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+    }
+}
+
+/// Compares two values the way the BINARY collation orders them on disk: text is compared as
+/// bytes in the database encoding, which is not code point order for UTF-16.
+pub fn compare_in_encoding(a: &Literal, b: &Literal, encoding: TextEncoding) -> Option<Ordering> {
+    match (a, b, encoding) {
+        (Literal::StringLiteral(a), Literal::StringLiteral(b), TextEncoding::Utf16le) => {
+            let a = a.encode_utf16().flat_map(u16::to_le_bytes);
+            let b = b.encode_utf16().flat_map(u16::to_le_bytes);
+            Some(a.cmp(b))
+        },
+        (Literal::StringLiteral(a), Literal::StringLiteral(b), TextEncoding::Utf16be) => Some(a.encode_utf16().cmp(b.encode_utf16())),
+        _ => a.partial_cmp(b),
+    }
+}
 
-        if schema_type == "table" {
-            tables.push(table);
-        } else if schema_type == "index" {
-            indices.push(table);
+/// Decodes every column of a record (header + body) into literals.
+pub fn parse_record(payload: &[u8], encoding: TextEncoding) -> Result<Vec<Literal>> {
+    let mut current_offset = 0;
+    let header_size = get_varint(payload, &mut current_offset) as usize; // record header size
+    let mut column_sizes = vec![];
+    while current_offset < header_size {
+        let csize = get_varint(payload, &mut current_offset);
+        column_sizes.push(csize);
+    }
+    if current_offset != header_size {
+        bail!("Did not get to the end of record header! Expected: {}, current: {}", header_size, current_offset)
+    }
+
+    let mut cols = vec![];
+    for column_size in column_sizes {
+        let size = get_column_size(column_size);
+        let ctype = get_column_type(column_size);
+        if current_offset + size > payload.len() {
+            bail!("Record body is shorter than its header describes")
+        }
+        if ctype == 13 {
+            let content = decode_text(&payload[current_offset..current_offset+size], encoding)?;
+            cols.push(Literal::StringLiteral(content));
+        } else if ctype == 12 {
+            cols.push(Literal::BlobLiteral(payload[current_offset..current_offset+size].to_vec()));
+        } else if ctype == 8 {
+            cols.push(Literal::IntegerLiteral(0));
+        } else if ctype == 9 {
+            cols.push(Literal::IntegerLiteral(1));
+        } else if ctype == 0 {
+            cols.push(Literal::Null);
+        } else if ctype == 7 {
+            let n = f64::from_bits(get_u64_from_size_n(&payload[current_offset..], size));
+            cols.push(Literal::RealLiteral(n));
+        } else if ctype > 0 && ctype < 7 {
+            let n = get_int_size_n(&payload[current_offset..], size);
+            cols.push(Literal::IntegerLiteral(n));
         } else {
-            panic!("Unrecognized schema type");
+            bail!("Ctype not recognized")
         }
+        current_offset += size;
     }
-    (tables, indices)
+    Ok(cols)
 }
 
-pub fn read_index(file: &File, page_num: u32, page_size: usize, where_expr: &Expr, available_columns: &Vec<Column>, print_columns: &Vec<usize>, indexed_columns: &Vec<Column>, table_page_num: u32) -> Result<()> {
-    let mut buffer  = Vec::new();
-    buffer.resize(page_size, 0u8);
-    let page_offset = (page_size*(page_num as usize - 1)) as u64;
-    file.read_exact_at(&mut buffer, page_offset)?; // read page
-    
-    let page_type = buffer[0];
-    let cell_num = u16::from_be_bytes([buffer[3], buffer[4]]);
+/// Encodes `values` as a record (header + body) that [`parse_record`] reads back, with text in UTF-8.
+pub fn encode_record(values: &[Literal]) -> Vec<u8> {
+    let mut types = vec![];
+    let mut body = vec![];
+    for value in values {
+        let serial_type = match value {
+            Literal::Null => 0,
+            Literal::IntegerLiteral(n) => {
+                let (serial_type, size) = match *n {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+                serial_type
+            },
+            Literal::RealLiteral(n) => {
+                body.extend_from_slice(&n.to_bits().to_be_bytes());
+                7
+            },
+            Literal::StringLiteral(s) | Literal::Ident(s) => {
+                body.extend_from_slice(s.as_bytes());
+                s.len() as u64 * 2 + 13
+            },
+            Literal::BlobLiteral(b) => {
+                body.extend_from_slice(b);
+                b.len() as u64 * 2 + 12
+            },
+        };
+        put_varint(&mut types, serial_type);
+    }
+    // the header size counts its own varint
+    let mut header_size = types.len() + 1;
+    let mut size_varint = vec![];
+    put_varint(&mut size_varint, header_size as u64);
+    if size_varint.len() > 1 {
+        header_size = types.len() + size_varint.len();
+        size_varint.clear();
+        put_varint(&mut size_varint, header_size as u64);
+    }
+    let mut record = size_varint;
+    record.extend(types);
+    record.extend(body);
+    record
+}
 
-    let where_col_num = indexed_columns.iter().position(|c| c.name == where_expr.get_equality().unwrap().0.get_ident().unwrap()).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::testing;
 
-    // leaf index
-    if page_type == 10 {
-        for i in 0..cell_num as usize {
-            let mut current_offset = u16::from_be_bytes([buffer[8+2*i], buffer[8+2*i+1]]) as usize;
-            get_varint(&buffer, &mut current_offset); // payload size
-            // start payload
-            get_varint(&buffer, &mut current_offset); // header size
-            // start header
-            let mut column_sizes = vec![];
-            for _ in 0..indexed_columns.len() {
-                let csize = get_varint(&buffer, &mut current_offset);
-                column_sizes.push(csize);
-            }
-            let rowid_size = get_varint(&buffer, &mut current_offset);
-            column_sizes.push(rowid_size);
-            let mut index_cols = vec![];
-            for j in 0..indexed_columns.len() {
-                let size = get_column_size(column_sizes[j]);
-                let ctype = get_column_type(column_sizes[j]);
-                if ctype == 13 {
-                    let content_bytes = buffer[current_offset..current_offset+size].to_vec();
-                    current_offset += size;
-                    let content = String::from_utf8(content_bytes)?;
-                    index_cols.push(Literal::StringLiteral(content));
-                } else if ctype == 8 {
-                    index_cols.push(Literal::NumberLiteral(0.0));
-                } else if ctype == 9 {
-                    index_cols.push(Literal::NumberLiteral(1.0));
-                } else if ctype == 0 {
-                    index_cols.push(Literal::Null);
-                } else if ctype > 0 && ctype < 8 {
-                    let n = get_num_size_n(&buffer[current_offset..], size, ctype == 7);
-                    current_offset += size;
-                    index_cols.push(Literal::NumberLiteral(n));
-                } else {
-                    bail!("Ctype not recognized")
-                }
-            }
-            let searching_col = &index_cols[where_col_num];
-            let filtered_value = &where_expr.get_equality()?.1;
-            if filtered_value < searching_col {
-                break;
-            } else if filtered_value > searching_col {
-            } else {
-                let rowid = get_u64_from_size_n(&buffer[current_offset..], rowid_size as usize);
-                read_page(file, table_page_num, page_size, &Some(where_expr.clone()), available_columns, print_columns, Some(rowid))?;
-            }
+    #[test]
+    fn local_payload_sizes() {
+        // 512 byte pages with 8 reserved bytes
+        assert_eq!(get_local_payload_size(469, 504, CellKind::TableLeaf), 469);
+        // the minimum local size is 38, topped up so that the overflow pages are full
+        assert_eq!(get_local_payload_size(470, 504, CellKind::TableLeaf), 38);
+        assert_eq!(get_local_payload_size(600, 504, CellKind::TableLeaf), 100);
+        assert_eq!(get_local_payload_size(100, 504, CellKind::Index), 100);
+        assert_eq!(get_local_payload_size(101, 504, CellKind::Index), 38);
+        assert_eq!(get_local_payload_size(540, 504, CellKind::Index), 40);
+    }
+
+    /// Body of row `n` of `testdata/overflow.db`.
+    fn body(n: usize) -> String {
+        (1..=n).map(|i| format!("{:04}", i)).collect::<Vec<_>>().join(",")
+    }
+
+    #[test]
+    fn reads_overflow_pages() {
+        let connection = testing::open("overflow");
+        assert_eq!(connection.pager().usable_size, 504);
+        let rows = connection.prepare("SELECT id, body FROM docs").unwrap().query().unwrap()
+            .map(|row| row.unwrap().into_values())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 6);
+        for row in rows {
+            let [Literal::IntegerLiteral(id), Literal::StringLiteral(text)] = &row[..] else { panic!("unexpected row {:?}", row) };
+            assert_eq!(*text, body(*id as usize));
         }
     }
-    // internal index
-    else if page_type == 2 {
-        let last_page = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
-        let mut search_right = true;
-        for i in 0..cell_num as usize {
-            let mut current_offset = u16::from_be_bytes([buffer[12+2*i], buffer[12+2*i+1]]) as usize;
-            let cell_page = u32::from_be_bytes([buffer[current_offset], buffer[current_offset+1], buffer[current_offset+2], buffer[current_offset+3]]);
-            current_offset += 4;
-            get_varint(&buffer, &mut current_offset); // payload size
-            // start payload
-            let header_start = current_offset;
-            let header_size = get_varint(&buffer, &mut current_offset) as usize; // header size
-            // start header
-            let mut column_sizes = vec![];
-            for _ in 0..indexed_columns.len() {
-                let csize = get_varint(&buffer, &mut current_offset);
-                column_sizes.push(csize);
-            }
-            let rowid_size = get_varint(&buffer, &mut current_offset);
-            column_sizes.push(rowid_size);
-            if header_start + header_size != current_offset {
-                bail!("bad header, currently in {}, expected {}", current_offset, header_start + header_size)
-            }
-            let mut index_cols = vec![];
-            for j in 0..indexed_columns.len() {
-                let size = get_column_size(column_sizes[j]);
-                let ctype = get_column_type(column_sizes[j]);
-                if ctype == 13 {
-                    let content_bytes = buffer[current_offset..current_offset+size].to_vec();
-                    current_offset += size;
-                    let content = String::from_utf8(content_bytes)?;
-                    index_cols.push(Literal::StringLiteral(content));
-                } else if ctype == 8 {
-                    index_cols.push(Literal::NumberLiteral(0.0));
-                } else if ctype == 9 {
-                    index_cols.push(Literal::NumberLiteral(1.0));
-                } else if ctype == 0 {
-                    index_cols.push(Literal::Null);
-                } else if ctype > 0 && ctype < 8 {
-                    let n = get_num_size_n(&buffer[current_offset..], size, ctype == 7);
-                    current_offset += size;
-                    index_cols.push(Literal::NumberLiteral(n));
-                } else {
-                    bail!("Ctype not recognized")
-                }
-            }
-            let searching_col = &index_cols[where_col_num];
-            let filtered_value = &where_expr.get_equality()?.1;
-            if filtered_value < searching_col {
-                read_index(file, cell_page, page_size, where_expr, available_columns, print_columns, indexed_columns, table_page_num)?;
-                search_right = false;
-                if !(&Literal::Null == searching_col) {
-                    break;
-                }
-            } else if filtered_value > searching_col {
-            } else {
-                let rowid = get_u64_from_size_n(&buffer[current_offset..], rowid_size as usize);
-                read_page(file, table_page_num, page_size, &Some(where_expr.clone()), available_columns, print_columns, Some(rowid))?;
-                read_index(file, cell_page, page_size, where_expr, available_columns, print_columns, indexed_columns, table_page_num)?;
-            }
+
+    #[test]
+    fn reads_overflowing_index_keys() {
+        let connection = testing::open("overflow");
+        assert_eq!(testing::query(&connection, "SELECT id FROM docs ORDER BY body DESC"), ["2000", "500", "101", "100", "20", "1"]);
+        for n in [20, 101, 2000] {
+            let sql = format!("SELECT id, length(body) FROM docs WHERE body = '{}'", body(n));
+            assert_eq!(testing::query(&connection, &sql), [format!("{}|{}", n, body(n).len())]);
         }
-        if search_right {
-            read_index(file, last_page, page_size, where_expr, available_columns, print_columns, indexed_columns, table_page_num)?;
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for (value, len) in [
+            (0, 1),
+            (0x7f, 1),
+            (0x80, 2),
+            (0x3fff, 2),
+            (0x4000, 3),
+            (0x1f_ffff, 3),
+            (1 << 49, 8),
+            ((1 << 56) - 1, 8),
+            (1 << 56, 9),
+            (u64::MAX, 9),
+            (-1i64 as u64, 9),
+        ] {
+            let mut out = vec![];
+            put_varint(&mut out, value);
+            assert_eq!(out.len(), len, "length of {:#x}", value);
+            let mut offset = 0;
+            assert_eq!(get_varint(&out, &mut offset) as u64, value);
+            assert_eq!(offset, len);
         }
-    } else {
-        bail!("({}) Unrecognized index page type: {}", line!(), page_type);
     }
-    Ok(())
-}
 
-pub fn read_page(file: &File, page_num: u32, page_size: usize, where_expr: &Option<Expr>, available_columns: &Vec<Column>, print_columns: &Vec<usize>, search_rowid: Option<u64>) -> Result<()> {
-    let mut buffer = Vec::new();
-    buffer.resize(page_size, 0u8);
-    let page_offset = (page_size*(page_num as usize - 1)) as u64;
-    file.read_exact_at(&mut buffer, page_offset)?; // read page
-    
-    let page_type = buffer[0];
-    let cell_num = u16::from_be_bytes([buffer[3], buffer[4]]);
+    #[test]
+    fn records_round_trip() {
+        let values = vec![
+            Literal::Null,
+            Literal::IntegerLiteral(0),
+            Literal::IntegerLiteral(-0x80),
+            Literal::IntegerLiteral(0x7fff),
+            Literal::IntegerLiteral(-0x80_0000),
+            Literal::IntegerLiteral(0x7fff_ffff),
+            Literal::IntegerLiteral(-0x8000_0000_0000),
+            Literal::IntegerLiteral(i64::MIN),
+            Literal::IntegerLiteral(i64::MAX),
+            Literal::RealLiteral(-2.5),
+            Literal::RealLiteral(0.0),
+            Literal::StringLiteral(String::new()),
+            Literal::StringLiteral("héllo".into()),
+            Literal::BlobLiteral(vec![0, 0xff, 0x10]),
+        ];
+        let record = encode_record(&values);
+        // equality of literals compares numbers across types, their debug text does not
+        assert_eq!(format!("{:?}", parse_record(&record, TextEncoding::Utf8).unwrap()), format!("{:?}", values));
+    }
 
-    // leaf page
-    if page_type == 13 {
-        // read each cell
-        for i in 0..cell_num as usize {
-            let mut current_offset = u16::from_be_bytes([buffer[8+2*i], buffer[8+2*i+1]]) as usize;
-            get_varint(&buffer, &mut current_offset); // size of record
-            let rowid = get_varint(&buffer, &mut current_offset) as u64; // the rowid
-            if let Some(search_rowid) = search_rowid {
-                if rowid < search_rowid {
-                    continue;
-                } else if rowid > search_rowid {
-                    break;
-                }
-            }
-            
-            // read cell header
-            let record_header_start = current_offset;
-            let record_header_size = get_varint(&buffer, &mut current_offset); // record header size
-            let mut column_sizes = vec![];
-            for _ in 0..available_columns.len() {
-                let csize = get_varint(&buffer, &mut current_offset);
-                column_sizes.push(csize);
-            }
-            if record_header_start + record_header_size as usize != current_offset {
-                bail!("Did not get to the end of record header! Expected: {}, current: {}", record_header_start + record_header_size as usize, current_offset)
-            }
-            
-            let mut cols = vec![];
-            
-            // read record content (loop for each column)
-            for j in 0..available_columns.len() {
-                if available_columns[j].name == "id" {
-                    cols.push(Literal::NumberLiteral(rowid as f64));
-                    continue;
-                }
-                let size = get_column_size(column_sizes[j]);
-                let ctype = get_column_type(column_sizes[j]);
-                if ctype == 13 {
-                    let content_bytes = buffer[current_offset..current_offset+size].to_vec();
-                    current_offset += size;
-                    let content = String::from_utf8(content_bytes)?;
-                    cols.push(Literal::StringLiteral(content));
-                } else if ctype == 8 {
-                    cols.push(Literal::NumberLiteral(0.0));
-                } else if ctype == 9 {
-                    cols.push(Literal::NumberLiteral(1.0));
-                } else if ctype == 0 {
-                    cols.push(Literal::Null);
-                } else if ctype > 0 && ctype < 8 {
-                    let n = get_num_size_n(&buffer[current_offset..], size, ctype == 7);
-                    current_offset += size;
-                    cols.push(Literal::NumberLiteral(n));
-                } else {
-                    bail!("Ctype not recognized")
-                }
-            }
-    
-            // check where clause
-            if let Some(expr) = where_expr.clone() {
-                if let Expr::Equality { column: Literal::Ident(column), condition } = expr {
-                    let cnum = available_columns.iter().position(|c| c.name == column).ok_or(anyhow!("Column not on table"))?;
-                    let cvalue = &cols[cnum];
-                    if cvalue != &condition {
-                        continue;
-                    }
-                }
-            }
-            
-            // print row
-            for (j, col_index) in print_columns.iter().enumerate() {
-                if j > 0 {
-                    print!("|");
-                }
-                print!("{}", cols[*col_index]);
-            }
-    
-            println!();
+    #[test]
+    fn records_with_long_headers_round_trip() {
+        // over 127 header bytes, so the header size itself takes two bytes
+        for count in [125, 126, 127, 200] {
+            let values = (0..count).map(|i| Literal::StringLiteral("x".repeat(i))).collect::<Vec<_>>();
+            let record = encode_record(&values);
+            assert_eq!(format!("{:?}", parse_record(&record, TextEncoding::Utf8).unwrap()), format!("{:?}", values));
+        }
+        for count in [126, 127, 128] {
+            let values = vec![Literal::Null; count];
+            assert_eq!(parse_record(&encode_record(&values), TextEncoding::Utf8).unwrap().len(), count);
         }
+        let values = vec![Literal::BlobLiteral(vec![7; 20_000])];
+        assert_eq!(format!("{:?}", parse_record(&encode_record(&values), TextEncoding::Utf8).unwrap()), format!("{:?}", values));
     }
-    // interior page
-    else if page_type == 5 {
-        let last_page = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
-        let mut search_right = true;
-        for i in 0..cell_num as usize {
-            let mut current_offset = u16::from_be_bytes([buffer[12+2*i], buffer[12+2*i+1]]) as usize;
-            let cell_page = u32::from_be_bytes([buffer[current_offset], buffer[current_offset+1], buffer[current_offset+2], buffer[current_offset+3]]);
-            current_offset += 4;
-            let rowid = get_varint(&buffer, &mut current_offset) as u64; // the rowid
-            if let Some(search_rowid) = search_rowid {
-                if rowid < search_rowid {
-                    continue;
-                } else if rowid > search_rowid {
-                    read_page(file, cell_page, page_size, where_expr, available_columns, print_columns, Some(search_rowid))?;
-                    search_right = false;
-                    break;
-                }
-            }
-            read_page(file, cell_page, page_size, where_expr, available_columns, print_columns, search_rowid)?;
+
+    #[test]
+    fn decodes_blob_and_text_serial_types() {
+        // header of 4 bytes: an empty blob (12), a 3 byte blob (18) and 3 bytes of text (19)
+        let record = [4, 12, 18, 19, 0x00, 0xff, 0x10, b'a', b'b', b'c'];
+        let values = parse_record(&record, TextEncoding::Utf8).unwrap();
+        assert_eq!(format!("{:?}", values), format!("{:?}", [
+            Literal::BlobLiteral(vec![]),
+            Literal::BlobLiteral(vec![0x00, 0xff, 0x10]),
+            Literal::StringLiteral("abc".into()),
+        ]));
+        assert!(parse_record(&record[..8], TextEncoding::Utf8).is_err());
+    }
+
+    #[test]
+    fn decodes_integer_and_real_serial_types() {
+        let mut record = vec![11, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
+        record.extend([0x80]);
+        record.extend([0x01, 0x00]);
+        record.extend([0x7f, 0xff, 0xff]);
+        record.extend([0x80, 0x00, 0x00, 0x00]);
+        record.extend([0x80, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        record.extend([0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        record.extend(2.5f64.to_be_bytes());
+        let values = parse_record(&record, TextEncoding::Utf8).unwrap();
+        assert_eq!(format!("{:?}", values), format!("{:?}", [
+            Literal::IntegerLiteral(-128),
+            Literal::IntegerLiteral(256),
+            Literal::IntegerLiteral(8388607),
+            Literal::IntegerLiteral(-2147483648),
+            Literal::IntegerLiteral(-140737488355328),
+            Literal::IntegerLiteral(9007199254740993),
+            Literal::RealLiteral(2.5),
+            Literal::IntegerLiteral(0),
+            Literal::IntegerLiteral(1),
+            Literal::Null,
+        ]));
+    }
+
+    #[test]
+    fn decodes_varints() {
+        for (bytes, value) in [
+            (&[0x00][..], 0),
+            (&[0x7f], 127),
+            (&[0x81, 0x00], 128),
+            (&[0x82, 0x2c], 300),
+            (&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], -1),
+            (&[0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00], 1 << 57),
+        ] {
+            let mut offset = 0;
+            assert_eq!(get_varint(bytes, &mut offset), value, "{:x?}", bytes);
+            assert_eq!(offset, bytes.len());
         }
-        if search_right {
-            read_page(file, last_page, page_size, where_expr, available_columns, print_columns, search_rowid)?;
+    }
+
+    #[test]
+    fn encodes_and_orders_utf16_text() {
+        assert_eq!(encode_text("Ä😀", TextEncoding::Utf16le), [0xc4, 0x00, 0x3d, 0xd8, 0x00, 0xde]);
+        assert_eq!(encode_text("Ä😀", TextEncoding::Utf16be), [0x00, 0xc4, 0xd8, 0x3d, 0xde, 0x00]);
+        assert_eq!(decode_text(&encode_text("日本", TextEncoding::Utf16be), TextEncoding::Utf16be).unwrap(), "日本");
+        // a surrogate pair sorts before U+FF61 in UTF-16 but after it in UTF-8
+        let (emoji, halfwidth) = (Literal::StringLiteral("😀".into()), Literal::StringLiteral("｡".into()));
+        assert_eq!(compare_in_encoding(&emoji, &halfwidth, TextEncoding::Utf8), Some(Ordering::Greater));
+        assert_eq!(compare_in_encoding(&emoji, &halfwidth, TextEncoding::Utf16be), Some(Ordering::Less));
+        assert_eq!(compare_in_encoding(&emoji, &halfwidth, TextEncoding::Utf16le), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn reads_utf16_databases() {
+        for (name, order) in [("utf16le", "4,1,5,6,2,3"), ("utf16be", "1,6,2,3,4,5")] {
+            let connection = testing::open(name);
+            assert_eq!(testing::query(&connection, "SELECT word FROM words WHERE id = 2"), ["Äpfel"]);
+            assert_eq!(testing::query(&connection, "SELECT id FROM words WHERE word = '日本'"), ["3"]);
+            assert_eq!(testing::query(&connection, "SELECT id FROM words WHERE word = '😀'"), ["4"]);
+            assert_eq!(testing::query(&connection, "SELECT id FROM words ORDER BY word").join(","), order);
         }
-    } else {
-        bail!("Unrecognized page type")
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,93 @@
+use crate::modules::{ast::{CreateIndexStatement, Expr, IndexKey, IndexedColumn}, table::Table};
+
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub rootpage: u32,
+    pub unique: bool,
+    pub columns: Vec<IndexedColumn>,
+    /// Condition of a partial index, only rows matching it are in the index
+    pub where_expr: Option<Expr>,
+}
+
+impl Index {
+    pub fn from_definition(definition: CreateIndexStatement, rootpage: u32) -> Self {
+        Self {
+            name: definition.name,
+            table: definition.table,
+            rootpage,
+            unique: definition.unique,
+            columns: definition.columns,
+            where_expr: definition.where_expr,
+        }
+    }
+
+    /// Whether rows of `table` matching a term on `column` of `where_expr` can be found by seeking this index.
+    pub fn can_seek(&self, table: &Table, column: &str, where_expr: &Expr) -> bool {
+        if !self.table.eq_ignore_ascii_case(&table.name) {
+            return false;
+        }
+        // a partial index only holds the rows matching its own condition
+        if self.where_expr.as_ref().is_some_and(|condition| condition != where_expr) {
+            return false;
+        }
+        let Some(IndexedColumn { key: IndexKey::Column(key), .. }) = self.columns.first() else {
+            return false;
+        };
+        if !key.eq_ignore_ascii_case(column) {
+            return false;
+        }
+        self.column_collation(table, 0).map_or(true, |collation| collation.eq_ignore_ascii_case("BINARY"))
+    }
+
+    /// Name of the collation the `i`th column of this index on `table` is ordered by, `None` for BINARY.
+    /// The index inherits the column collation unless it declares its own.
+    pub fn column_collation<'a>(&'a self, table: &'a Table, i: usize) -> Option<&'a str> {
+        let column = self.columns.get(i)?;
+        column.collation.as_deref().or_else(|| match &column.key {
+            IndexKey::Column(name) => table.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))?.collation.as_deref(),
+            IndexKey::Expression(_) => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::sql_parser::sql_parser;
+
+    fn index(sql: &str) -> Index {
+        Index::from_definition(sql_parser::create_index(sql).unwrap(), 3)
+    }
+
+    fn table() -> Table {
+        Table::from_definition(sql_parser::create_table("CREATE TABLE t(a, b COLLATE NOCASE, c)").unwrap(), 2)
+    }
+
+    #[test]
+    fn seeks_on_the_first_binary_column() {
+        let where_expr = sql_parser::expression("a = 1").unwrap();
+        assert!(index("CREATE INDEX i ON t(a, b)").can_seek(&table(), "A", &where_expr));
+        assert!(!index("CREATE INDEX i ON t(b, a)").can_seek(&table(), "a", &where_expr));
+        assert!(!index("CREATE INDEX i ON other(a)").can_seek(&table(), "a", &where_expr));
+        assert!(!index("CREATE INDEX i ON t(a COLLATE NOCASE)").can_seek(&table(), "a", &where_expr));
+        assert!(!index("CREATE INDEX i ON t(b)").can_seek(&table(), "b", &where_expr));
+        assert!(index("CREATE INDEX i ON t(b COLLATE BINARY)").can_seek(&table(), "b", &where_expr));
+        assert!(!index("CREATE INDEX i ON t(a + 1)").can_seek(&table(), "a", &where_expr));
+    }
+
+    #[test]
+    fn partial_indexes_only_serve_their_condition() {
+        let partial = index("CREATE INDEX i ON t(a) WHERE c IS NOT NULL");
+        assert!(partial.can_seek(&table(), "a", &sql_parser::expression("c IS NOT NULL").unwrap()));
+        assert!(!partial.can_seek(&table(), "a", &sql_parser::expression("a = 1").unwrap()));
+    }
+
+    #[test]
+    fn columns_inherit_the_table_collation() {
+        let index = index("CREATE INDEX i ON t(a, b, c COLLATE RTRIM, b COLLATE BINARY, lower(a))");
+        let table = table();
+        let collations = (0..6).map(|i| index.column_collation(&table, i)).collect::<Vec<_>>();
+        assert_eq!(collations, [None, Some("NOCASE"), Some("RTRIM"), Some("BINARY"), None, None]);
+    }
+}
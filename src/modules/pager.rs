@@ -0,0 +1,72 @@
+use std::{fs::File, os::unix::fs::FileExt};
+
+use anyhow::{Result, bail};
+
+use crate::modules::header::{DatabaseHeader, TextEncoding};
+
+/// Reads pages of the database file.
+pub struct Pager {
+    file: File,
+    pub page_size: usize,
+    /// Page size minus the bytes reserved at the end of every page by extensions (header byte 20)
+    pub usable_size: usize,
+    pub text_encoding: TextEncoding,
+}
+
+impl Pager {
+    pub fn new(file: File, header: &DatabaseHeader) -> Result<Self> {
+        let page_size = header.page_size as usize;
+        let usable_size = page_size - header.reserved_bytes as usize;
+        // the file format requires at least 480 usable bytes per page
+        if usable_size < 480 {
+            bail!("Usable page size {} is too small", usable_size)
+        }
+        Ok(Self { file, page_size, usable_size, text_encoding: header.text_encoding })
+    }
+
+    /// Reads a whole page, pages are numbered from 1.
+    pub fn read_page(&self, page_num: u32) -> Result<Vec<u8>> {
+        if page_num == 0 {
+            bail!("Page 0 does not exist")
+        }
+        let mut buffer = vec![0u8; self.page_size];
+        let page_offset = (self.page_size*(page_num as usize - 1)) as u64;
+        self.file.read_exact_at(&mut buffer, page_offset)?; // read page
+        Ok(buffer)
+    }
+
+    /// The reserved bytes at the end of a page, which the b-tree layer never uses.
+    pub fn reserved_region(&self, page_num: u32) -> Result<Vec<u8>> {
+        Ok(self.read_page(page_num)?.split_off(self.usable_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::modules::testing;
+
+    #[test]
+    fn pages_end_with_the_reserved_bytes() {
+        let connection = testing::open("overflow");
+        let pager = connection.pager();
+        assert_eq!((pager.page_size, pager.usable_size), (512, 504));
+        assert_eq!(connection.header().reserved_bytes, 8);
+        for page_num in [1, 2, 10] {
+            assert_eq!(pager.reserved_region(page_num).unwrap().len(), 8);
+        }
+    }
+
+    #[test]
+    fn rejects_pages_with_too_few_usable_bytes() {
+        let path = [env!("CARGO_MANIFEST_DIR"), "testdata", "overflow.db"].iter().collect::<std::path::PathBuf>();
+        let mut file = File::open(path).unwrap();
+        let mut bytes = [0; 100];
+        file.read_exact(&mut bytes).unwrap();
+        bytes[20] = 33;
+        let header = DatabaseHeader::parse(&bytes).unwrap();
+        assert!(Pager::new(file, &header).is_err());
+    }
+}
@@ -0,0 +1,831 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::modules::{
+    ast::{CompareOp, Expr, IndexKey, Literal, OrderingTerm, SelectItem, SelectStatement, SortOrder},
+    btree::BTreeCursor,
+    eval::{Collation, Scope},
+    executor::{Aggregate, Distinct, Filter, IndexScan, KeyBound, Limit, NestedLoopJoin, Operator, Project, RowidScan, Sort, SortKey, TableScan},
+    header::TextEncoding,
+    index::Index,
+    pager::Pager,
+    schema::Schema,
+    table::{Affinity, Table},
+};
+
+/// Root operator of a SELECT and the names of the columns it returns.
+pub struct Plan<'a> {
+    pub root: Box<dyn Operator + 'a>,
+    pub column_names: Vec<String>,
+}
+
+/// Builds the operator tree for `select`: a scan or seek of the table, or nested loops over the
+/// tables of a join, the WHERE filter, a sort
+/// unless the rows are read in the requested order already, or for queries with aggregates or a
+/// GROUP BY the aggregate, HAVING filter and sort of the groups, then the projection of the
+/// selected columns, DISTINCT, and finally LIMIT and OFFSET.
+pub fn plan_select<'a>(pager: &'a Pager, schema: &'a Schema, select: &SelectStatement, sort_memory_budget: usize) -> Result<Plan<'a>> {
+    let sources = from_sources(schema, select)?;
+    let mut scope: Option<Scope> = None;
+    for source in &sources {
+        let table = source.table;
+        if table.rootpage <= 1 {
+            bail!("table not found")
+        }
+        if table.without_rowid {
+            bail!("WITHOUT ROWID tables are not supported")
+        }
+        let table_scope = Scope::of_table(table, pager.text_encoding);
+        // VIRTUAL columns are computed for every row read, so their expressions must all work
+        for column in table.columns.iter().filter(|column| column.is_virtual) {
+            if column.generated.as_ref().map_or(true, |expr| table_scope.check(expr).is_err()) {
+                bail!("unsupported generated column: {}", column.name)
+            }
+        }
+        let table_scope = if source.qualified { table_scope.qualified(&source.qualifier) } else { table_scope };
+        scope = Some(match scope {
+            Some(scope) => Scope::join(&scope, &table_scope),
+            None => table_scope,
+        });
+    }
+    let scope = scope.ok_or(anyhow!("no tables specified"))?;
+    let select = &resolve_columns(&resolve_aliases(select, &sources), &sources)?;
+    if let Some(where_expr) = &select.where_expr {
+        scope.check(where_expr)?;
+    }
+
+    let mut column_names = vec![];
+    let mut exprs = vec![];
+    for column in &select.columns {
+        match column {
+            SelectItem::Star => {
+                for source in &sources {
+                    for (i, column) in source.table.columns.iter().enumerate() {
+                        column_names.push(column.name.clone());
+                        exprs.push(Expr::Literal(Literal::Ident(source.column_name(i))));
+                    }
+                }
+            },
+            SelectItem::Expr { expr, name, .. } => {
+                column_names.push(name.clone());
+                exprs.push(expr.clone());
+            },
+        }
+    }
+    let group_by = group_terms(&select.group_by, &exprs, &scope)?;
+    // aggregates in the result or the HAVING fold the rows that pass the WHERE into one per group
+    let mut aggregates = vec![];
+    for call in exprs.iter().chain(&select.having).flat_map(Expr::aggregates) {
+        if !aggregates.contains(call) {
+            aggregates.push(call.clone());
+        }
+    }
+    let aggregated = !aggregates.is_empty() || !group_by.is_empty();
+    if !aggregated && select.having.is_some() {
+        bail!("HAVING clause on a non-aggregate query")
+    }
+    if aggregated {
+        // ORDER BY sorts the groups, and may order them by aggregates of their own
+        for call in select.order_by.iter().flat_map(|term| term.expr.aggregates()) {
+            if !aggregates.contains(call) {
+                aggregates.push(call.clone());
+            }
+        }
+    } else if let Some(call) = select.order_by.iter().find_map(|term| term.expr.aggregates().first().copied()) {
+        bail!("misuse of aggregate: {}()", call.function.name())
+    }
+    let output_scope = if aggregated { Scope::aggregated(&scope, aggregates.clone()) } else { scope.clone() };
+    for expr in exprs.iter().chain(&select.having) {
+        output_scope.check(expr)?;
+    }
+    // the table is read in the order of the groups when an index allows, else of the ORDER BY, else
+    // in an order that brings equal rows together for DISTINCT
+    let order_keys = if aggregated { vec![] } else { sort_keys(&select.order_by, &exprs, &scope)? };
+    let group_keys = grouping_keys(&group_by, &scope);
+    let distinct_keys = grouping_keys(&exprs, &output_scope);
+    let streams_distinct = select.distinct && !aggregated && order_keys.is_empty();
+    let read_order = if aggregated { &group_keys } else if streams_distinct { &distinct_keys } else { &order_keys };
+
+    let (mut root, reverse) = match &sources[..] {
+        [source] => open_table(pager, schema, source.table, select.where_expr.as_ref(), read_order, aggregated || streams_distinct),
+        _ => (join_tables(pager, &sources), None),
+    };
+    if let Some(where_expr) = &select.where_expr {
+        root = Box::new(Filter::new(root, where_expr.clone(), scope.clone()));
+    }
+    if reverse.is_none() && !order_keys.is_empty() {
+        root = Box::new(Sort::new(root, order_keys, scope.clone()).with_memory_budget(sort_memory_budget));
+    }
+
+    let (limit, offset) = limit_bounds(select, pager.text_encoding)?;
+    if aggregated {
+        let aggregate = Aggregate::new(root, group_by.clone(), aggregates, scope.clone()).with_memory_budget(sort_memory_budget);
+        root = Box::new(if reverse.is_some() && !group_by.is_empty() { aggregate.streaming() } else { aggregate });
+        if let Some(having) = &select.having {
+            root = Box::new(Filter::new(root, having.clone(), output_scope.clone()));
+        }
+        // a single aggregate row has no order to speak of
+        if !group_by.is_empty() {
+            let group_order = sort_keys(&select.order_by, &exprs, &output_scope)?;
+            if !group_order.is_empty() {
+                root = Box::new(Sort::new(root, group_order, output_scope.clone()).with_memory_budget(sort_memory_budget));
+            }
+        }
+    }
+    root = Box::new(Project::new(root, exprs, output_scope));
+    if select.distinct {
+        let distinct = Distinct::new(root, distinct_keys.into_iter().map(|key| key.collation).collect()).with_memory_budget(sort_memory_budget);
+        root = Box::new(if streams_distinct && reverse.is_some() { distinct.streaming() } else { distinct });
+    }
+    if limit.is_none() && offset == 0 {
+        return Ok(Plan { root, column_names });
+    }
+    // the scan below only reads pages as rows are pulled, so stopping here stops the reads
+    Ok(Plan { root: Box::new(Limit::new(root, limit, offset)), column_names })
+}
+
+/// A table of the FROM clause.
+struct Source<'a> {
+    table: &'a Table,
+    /// The alias of the table, or else its name
+    qualifier: String,
+    /// Whether the columns are named `qualifier.column` in the rows, as they are in a join
+    qualified: bool,
+}
+
+impl Source<'_> {
+    /// Name of the `i`th column, or of the rowid after the last one, in the scope of the query.
+    fn column_name(&self, i: usize) -> String {
+        let name = self.table.columns.get(i).map_or("rowid", |column| &column.name);
+        if self.qualified { format!("{}.{}", self.qualifier, name) } else { name.to_string() }
+    }
+}
+
+fn from_sources<'a>(schema: &'a Schema, select: &SelectStatement) -> Result<Vec<Source<'a>>> {
+    let tables = std::iter::once((&select.table, &select.table_alias)).chain(select.joins.iter().map(|join| (&join.table, &join.alias)));
+    tables.map(|(name, alias)| Ok(Source {
+        table: schema.table(name)?,
+        qualifier: alias.clone().unwrap_or_else(|| name.clone()),
+        qualified: !select.joins.is_empty(),
+    })).collect()
+}
+
+/// The name a column reference has in the scope of the query, `None` when it is no column of the
+/// FROM tables, so may be a result column alias.
+fn source_column(sources: &[Source], name: &str) -> Result<Option<String>> {
+    if let Some((qualifier, column)) = name.split_once('.') {
+        if let Some(source) = sources.iter().find(|source| source.qualifier.eq_ignore_ascii_case(qualifier)) {
+            let i = source.table.column_index(column).ok_or(anyhow!("no such column: {}", name))?;
+            return Ok(Some(source.column_name(i)));
+        }
+    }
+    let mut found = sources.iter().filter_map(|source| Some(source.column_name(source.table.column_index(name)?)));
+    match (found.next(), found.next()) {
+        (Some(column), None) => Ok(Some(column)),
+        (Some(_), Some(_)) => bail!("ambiguous column name: {}", name),
+        (None, _) if name.contains('.') => bail!("no such column: {}", name),
+        (None, _) => Ok(None),
+    }
+}
+
+/// Names every column reference the way the scope of the query does, and for a join adds the ON
+/// conditions to the WHERE clause.
+fn resolve_columns(select: &SelectStatement, sources: &[Source]) -> Result<SelectStatement> {
+    fn check(expr: &Expr, sources: &[Source]) -> Result<()> {
+        match expr {
+            Expr::Literal(Literal::Ident(name)) => source_column(sources, name).map(|_| ()),
+            expr => expr.children().into_iter().try_for_each(|child| check(child, sources)),
+        }
+    }
+    let resolve = |expr: &Expr| -> Result<Expr> {
+        check(expr, sources)?;
+        Ok(expr.replace_columns(&|name| source_column(sources, name).ok().flatten().map(|column| Expr::Literal(Literal::Ident(column)))))
+    };
+    let where_expr = select.joins.iter().filter_map(|join| join.constraint.clone()).chain(select.where_expr.clone())
+        .map(|expr| resolve(&expr))
+        .reduce(|left, right| Ok(Expr::And(Box::new(left?), Box::new(right?))))
+        .transpose()?;
+    Ok(SelectStatement {
+        columns: select.columns.iter().map(|column| match column {
+            SelectItem::Expr { expr, name, alias } => Ok(SelectItem::Expr { expr: resolve(expr)?, name: name.clone(), alias: alias.clone() }),
+            SelectItem::Star => Ok(SelectItem::Star),
+        }).collect::<Result<_>>()?,
+        where_expr,
+        group_by: select.group_by.iter().map(resolve).collect::<Result<_>>()?,
+        having: select.having.as_ref().map(resolve).transpose()?,
+        order_by: select.order_by.iter().map(|term| Ok(OrderingTerm { expr: resolve(&term.expr)?, ..term.clone() })).collect::<Result<_>>()?,
+        joins: vec![],
+        ..select.clone()
+    })
+}
+
+/// Reads the table through the best access path for the WHERE clause, in `read_order` when an
+/// index or the rowid allows. Returns whether the rows come in that order, backwards or not, or
+/// `None` when they need sorting. Rows that only need grouping may come either way.
+fn open_table<'a>(pager: &'a Pager, schema: &'a Schema, table: &'a Table, where_expr: Option<&Expr>, read_order: &[SortKey], grouping: bool) -> (Box<dyn Operator + 'a>, Option<bool>) {
+    let mut path = choose_access(schema, table, where_expr);
+    let mut reverse = path_order(&path, table, read_order);
+    if reverse.is_none() && matches!(path, AccessPath::TableScan) && !read_order.is_empty() {
+        // no term narrows the scan, so read the whole table through an index in the right order
+        let ordered = schema.indices.iter()
+            .filter(|index| index.table.eq_ignore_ascii_case(&table.name) && index.where_expr.is_none())
+            .find_map(|index| Some((index, index_order(index, table, read_order, false)?)));
+        if let Some((index, index_reverse)) = ordered {
+            path = AccessPath::Index { index, lower: None, upper: None };
+            reverse = Some(index_reverse);
+        }
+    }
+    if grouping {
+        // groups only need their rows next to each other, which holds in either direction
+        reverse = reverse.map(|_| false);
+    }
+    (open_access(pager, table, path, reverse.unwrap_or(false)), reverse)
+}
+
+/// Every combination of rows of the tables, the first table in the outermost loop.
+fn join_tables<'a>(pager: &'a Pager, sources: &[Source<'a>]) -> Box<dyn Operator + 'a> {
+    let scan = |source: &Source<'a>| -> Box<dyn Operator + 'a> {
+        Box::new(TableScan::new(source.table, BTreeCursor::table(pager, source.table.rootpage)))
+    };
+    sources[1..].iter().fold(scan(&sources[0]), |left, source| Box::new(NestedLoopJoin::new(left, scan(source))))
+}
+
+/// Replaces the result column aliases used in the other clauses by the aliased expressions. A whole
+/// ORDER BY term names an alias before a column, elsewhere table columns hide aliases of the same name.
+fn resolve_aliases(select: &SelectStatement, sources: &[Source]) -> SelectStatement {
+    let aliases = select.columns.iter().filter_map(|column| match column {
+        SelectItem::Expr { expr, alias: Some(alias), .. } => Some((alias, expr)),
+        _ => None,
+    }).collect::<Vec<_>>();
+    if aliases.is_empty() {
+        return select.clone();
+    }
+    let alias = |name: &str| aliases.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(name)).map(|(_, expr)| (*expr).clone());
+    let is_column = |name: &str| !matches!(source_column(sources, name), Ok(None));
+    let resolve = |expr: &Expr| expr.replace_columns(&|name| if is_column(name) { None } else { alias(name) });
+    SelectStatement {
+        where_expr: select.where_expr.as_ref().map(resolve),
+        group_by: select.group_by.iter().map(resolve).collect(),
+        having: select.having.as_ref().map(resolve),
+        order_by: select.order_by.iter().map(|term| OrderingTerm {
+            expr: match &term.expr {
+                Expr::Literal(Literal::Ident(name)) => alias(name).unwrap_or_else(|| term.expr.clone()),
+                expr => resolve(expr),
+            },
+            ..term.clone()
+        }).collect(),
+        ..select.clone()
+    }
+}
+
+/// Evaluates LIMIT and OFFSET, which must be integers. A negative limit means none, a negative offset zero.
+fn limit_bounds(select: &SelectStatement, encoding: TextEncoding) -> Result<(Option<u64>, u64)> {
+    let scope = Scope::new(vec![], encoding);
+    let integer = |expr: &Expr| -> Result<i64> {
+        scope.check(expr)?;
+        match Affinity::Integer.apply(scope.eval(expr, &[])?) {
+            Literal::IntegerLiteral(n) => Ok(n),
+            _ => bail!("datatype mismatch"),
+        }
+    };
+    let limit = select.limit.as_ref().map(integer).transpose()?.and_then(|n| u64::try_from(n).ok());
+    let offset = select.offset.as_ref().map(integer).transpose()?.map_or(0, |n| u64::try_from(n).unwrap_or(0));
+    Ok((limit, offset))
+}
+
+/// Resolves the ORDER BY terms: a number picks a result column, the collation defaults to the column's.
+fn sort_keys(order_by: &[OrderingTerm], exprs: &[Expr], scope: &Scope) -> Result<Vec<SortKey>> {
+    order_by.iter().enumerate().map(|(i, term)| {
+        let expr = resolve_result_term(&term.expr, i, "ORDER", exprs)?;
+        scope.check(&expr)?;
+        let collation = match &term.collation {
+            Some(name) => Collation::from_name(name).ok_or(anyhow!("no such collation sequence: {}", name))?,
+            None => scope.collation(&expr).unwrap_or(Collation::Binary),
+        };
+        Ok(SortKey { expr, order: term.order, nulls_first: term.nulls_first(), collation })
+    }).collect()
+}
+
+/// Keys that order rows so that rows with equal `exprs` come together, like GROUP BY and DISTINCT need.
+fn grouping_keys(exprs: &[Expr], scope: &Scope) -> Vec<SortKey> {
+    exprs.iter().map(|expr| SortKey {
+        expr: expr.clone(),
+        order: SortOrder::Asc,
+        nulls_first: true,
+        collation: scope.collation(expr).unwrap_or(Collation::Binary),
+    }).collect()
+}
+
+/// Resolves the GROUP BY terms, where a number picks a result column like in ORDER BY.
+fn group_terms(terms: &[Expr], exprs: &[Expr], scope: &Scope) -> Result<Vec<Expr>> {
+    terms.iter().enumerate().map(|(i, term)| {
+        let expr = resolve_result_term(term, i, "GROUP", exprs)?;
+        if !expr.aggregates().is_empty() {
+            bail!("aggregate functions are not allowed in the GROUP BY clause")
+        }
+        scope.check(&expr)?;
+        Ok(expr)
+    }).collect()
+}
+
+/// The expression of the `i`th term of an ORDER or GROUP `clause`, where a number picks that result column.
+fn resolve_result_term(term: &Expr, i: usize, clause: &str, exprs: &[Expr]) -> Result<Expr> {
+    match term {
+        Expr::Literal(Literal::IntegerLiteral(n)) => {
+            let column = usize::try_from(*n).ok().and_then(|n| n.checked_sub(1)).and_then(|n| exprs.get(n));
+            let column = column.ok_or(anyhow!("{} {} BY term out of range - should be between 1 and {}", ordinal(i + 1), clause, exprs.len()))?;
+            Ok(column.clone())
+        },
+        expr => Ok(expr.clone()),
+    }
+}
+
+/// `1st`, `2nd`, `3rd`, `4th`... as sqlite3 words error messages.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// How the rows of the table are read.
+enum AccessPath<'a> {
+    TableScan,
+    /// Rows whose rowid is in one of the sorted ranges
+    Rowid(Vec<RangeInclusive<i64>>),
+    /// Rows whose first index column lies between the bounds, which are in value order
+    Index {
+        index: &'a Index,
+        lower: Option<KeyBound>,
+        upper: Option<KeyBound>,
+    },
+}
+
+/// Picks how to read the table: a seek on the rowid, a range of an index, or a full scan. Rowid
+/// equalities come first, then index equalities, then rowid ranges and index ranges.
+fn choose_access<'a>(schema: &'a Schema, table: &Table, where_expr: Option<&Expr>) -> AccessPath<'a> {
+    let rowid_ranges = where_expr.and_then(|where_expr| rowid_ranges(table, where_expr));
+    let index_range = index_range(schema, table, where_expr);
+    let rowid_points = rowid_ranges.as_ref().is_some_and(|ranges| ranges.iter().all(|range| range.start() == range.end()));
+    let index_equality = index_range.as_ref().is_some_and(|(_, lower, upper)| lower.is_some() && lower == upper);
+    match (rowid_ranges, index_range) {
+        (Some(ranges), _) if rowid_points || !index_equality => AccessPath::Rowid(ranges),
+        (_, Some((index, lower, upper))) => AccessPath::Index { index, lower, upper },
+        _ => AccessPath::TableScan,
+    }
+}
+
+fn open_access<'a>(pager: &'a Pager, table: &'a Table, path: AccessPath<'a>, reverse: bool) -> Box<dyn Operator + 'a> {
+    let table_cursor = BTreeCursor::table(pager, table.rootpage);
+    match path {
+        AccessPath::TableScan => {
+            let scan = TableScan::new(table, table_cursor);
+            Box::new(if reverse { scan.reversed() } else { scan })
+        },
+        AccessPath::Rowid(ranges) => {
+            let scan = RowidScan::new(table, table_cursor, ranges);
+            Box::new(if reverse { scan.reversed() } else { scan })
+        },
+        AccessPath::Index { index, lower, upper } => {
+            let orders = index.columns.iter().map(|column| column.order).collect::<Vec<_>>();
+            // a DESC index stores the largest values first
+            let (lower, upper) = if orders[0] == SortOrder::Desc { (upper, lower) } else { (lower, upper) };
+            let index_cursor = BTreeCursor::index(pager, index.rootpage, orders);
+            let scan = IndexScan::new(table, index_cursor, table_cursor, lower, upper);
+            Box::new(if reverse { scan.reversed() } else { scan })
+        },
+    }
+}
+
+/// Whether `path` reads rows in the order of `keys`: `Some(false)` as it is, `Some(true)` when
+/// walked backwards, `None` if the rows need sorting.
+fn path_order(path: &AccessPath, table: &Table, keys: &[SortKey]) -> Option<bool> {
+    match path {
+        AccessPath::TableScan | AccessPath::Rowid(_) => match keys {
+            [key] if is_rowid(table, &key.expr) => Some(key.order == SortOrder::Desc),
+            _ => None,
+        },
+        AccessPath::Index { index, lower, upper } => index_order(index, table, keys, lower.is_some() && lower == upper),
+    }
+}
+
+/// Whether walking `index` gives rows in the order of `keys`, and in which direction. When the
+/// first index column is fixed by an equality, the keys may also start at the second one.
+fn index_order(index: &Index, table: &Table, keys: &[SortKey], first_fixed: bool) -> Option<bool> {
+    let skips = if first_fixed { 0..=1 } else { 0..=0 };
+    skips.filter_map(|skip| {
+        let columns = index.columns.get(skip..)?;
+        if keys.is_empty() || keys.len() > columns.len() {
+            return None;
+        }
+        let mut reverse = None;
+        for (i, (key, column)) in keys.iter().zip(columns).enumerate() {
+            let (IndexKey::Column(name), Expr::Literal(Literal::Ident(key_name))) = (&column.key, &key.expr) else {
+                return None;
+            };
+            let position = table.column_index(name)?;
+            if table.column_index(key_name) != Some(position) || position == table.columns.len() {
+                return None;
+            }
+            let collation = index.column_collation(table, skip + i);
+            if collation.and_then(Collation::from_name).unwrap_or(Collation::Binary) != key.collation {
+                return None;
+            }
+            let key_reverse = key.order != column.order;
+            if reverse.is_some_and(|reverse| reverse != key_reverse) {
+                return None;
+            }
+            reverse = Some(key_reverse);
+            // NULLs are the smallest values, so they come first when walking up the stored order
+            let nulls_first = (column.order == SortOrder::Asc) != key_reverse;
+            if nulls_first != key.nulls_first {
+                return None;
+            }
+        }
+        reverse
+    }).next()
+}
+
+fn is_rowid(table: &Table, expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Ident(column)) => {
+            let i = table.column_index(column);
+            i == Some(table.columns.len()) || (i.is_some() && i == table.rowid_alias)
+        },
+        _ => false,
+    }
+}
+
+/// Rowids the WHERE can match according to its terms on the rowid, as sorted disjoint ranges.
+/// `None` when no term constrains the rowid.
+fn rowid_ranges(table: &Table, where_expr: &Expr) -> Option<Vec<RangeInclusive<i64>>> {
+    where_expr.conjuncts().into_iter()
+        .filter_map(|term| rowid_term_ranges(table, term))
+        .reduce(|matching, ranges| intersect(&matching, &ranges))
+}
+
+/// Rowids a single WHERE term allows, if it compares the rowid with constants.
+fn rowid_term_ranges(table: &Table, term: &Expr) -> Option<Vec<RangeInclusive<i64>>> {
+    let is_rowid = |expr: &Expr| is_rowid(table, expr);
+    let constant = |expr: &Expr| match expr {
+        Expr::Literal(Literal::Ident(_)) => None,
+        Expr::Literal(value) => Some(Affinity::Integer.apply(value.clone())),
+        _ => None,
+    };
+    match term {
+        Expr::Compare { op: CompareOp::Ne, .. } => None,
+        Expr::Compare { op, left, right } if is_rowid(left) => Some(rowid_compare(*op, &constant(right)?)),
+        Expr::Compare { op, left, right } if is_rowid(right) => Some(rowid_compare(op.flip(), &constant(left)?)),
+        Expr::Between { expr, low, high, negated: false } if is_rowid(expr) => {
+            Some(intersect(&rowid_compare(CompareOp::Ge, &constant(low)?), &rowid_compare(CompareOp::Le, &constant(high)?)))
+        },
+        Expr::In { expr, list, negated: false } if is_rowid(expr) => {
+            let mut points = list.iter().map(|item| Some(rowid_compare(CompareOp::Eq, &constant(item)?))).collect::<Option<Vec<_>>>()?.concat();
+            points.sort_by_key(|point| *point.start());
+            points.dedup();
+            Some(points)
+        },
+        _ => None,
+    }
+}
+
+/// Rowids `rowid <op> value` holds for. Text and blobs sort after every number and NULL matches nothing.
+fn rowid_compare(op: CompareOp, value: &Literal) -> Vec<RangeInclusive<i64>> {
+    const TWO_POW_63: f64 = 9223372036854775808.0;
+    let (below, above) = match value {
+        Literal::IntegerLiteral(n) => match op {
+            CompareOp::Eq => return vec![*n..=*n],
+            CompareOp::Ne => return vec![i64::MIN..=i64::MAX],
+            CompareOp::Lt => (n.checked_sub(1), None),
+            CompareOp::Le => (Some(*n), None),
+            CompareOp::Gt => (None, n.checked_add(1)),
+            CompareOp::Ge => (None, Some(*n)),
+        },
+        Literal::RealLiteral(r) if r.is_nan() => return vec![],
+        Literal::RealLiteral(r) => match op {
+            // Integer affinity already turned integral reals into integers
+            CompareOp::Eq => return vec![],
+            CompareOp::Ne => return vec![i64::MIN..=i64::MAX],
+            CompareOp::Lt | CompareOp::Le if *r >= TWO_POW_63 => return vec![i64::MIN..=i64::MAX],
+            CompareOp::Lt | CompareOp::Le => ((*r >= -TWO_POW_63).then(|| r.floor() as i64), None),
+            CompareOp::Gt | CompareOp::Ge if *r < -TWO_POW_63 => return vec![i64::MIN..=i64::MAX],
+            CompareOp::Gt | CompareOp::Ge => (None, (*r < TWO_POW_63).then(|| r.ceil() as i64)),
+        },
+        Literal::Null => return vec![],
+        _ => match op {
+            CompareOp::Lt | CompareOp::Le | CompareOp::Ne => return vec![i64::MIN..=i64::MAX],
+            _ => return vec![],
+        },
+    };
+    match (below, above) {
+        (Some(end), _) => vec![i64::MIN..=end],
+        (_, Some(start)) => vec![start..=i64::MAX],
+        _ => vec![],
+    }
+}
+
+/// Intersection of two lists of sorted disjoint ranges.
+fn intersect(a: &[RangeInclusive<i64>], b: &[RangeInclusive<i64>]) -> Vec<RangeInclusive<i64>> {
+    let mut ranges = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let start = *a[i].start().max(b[j].start());
+        let end = *a[i].end().min(b[j].end());
+        if start <= end {
+            ranges.push(start..=end);
+        }
+        if a[i].end() < b[j].end() {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    ranges
+}
+
+/// Picks an index to narrow the scan with and the range of its first column to read. An equality
+/// wins over a range, and an open end of a range is closed by other terms on the same column.
+/// The WHERE filter still runs on every row, so the range only has to include all matches.
+fn index_range<'a>(schema: &'a Schema, table: &Table, where_expr: Option<&Expr>) -> Option<(&'a Index, Option<KeyBound>, Option<KeyBound>)> {
+    let where_expr = where_expr?;
+    let terms = where_expr.conjuncts().into_iter().filter_map(column_bounds).collect::<Vec<_>>();
+    let seekable = |column: &str| schema.indices.iter().find(|index| index.can_seek(table, column, where_expr));
+    let (column, index, mut lower, mut upper) = terms.iter()
+        .filter(|(_, lower, upper)| lower.is_some() && lower == upper)
+        .chain(&terms)
+        .find_map(|(column, lower, upper)| Some((*column, seekable(column)?, lower.clone(), upper.clone())))?;
+    for (other, other_lower, other_upper) in &terms {
+        if other.eq_ignore_ascii_case(column) {
+            lower = lower.or_else(|| other_lower.clone());
+            upper = upper.or_else(|| other_upper.clone());
+        }
+    }
+    // the index holds values converted to the column affinity, so the bounds must be too
+    let affinity = table.columns.get(table.column_index(column)?)?.affinity();
+    let convert = |bound: Option<KeyBound>| bound.map(|bound| KeyBound {
+        key: bound.key.into_iter().map(|value| affinity.apply(value)).collect(),
+        inclusive: bound.inclusive,
+    });
+    Some((index, convert(lower), convert(upper)))
+}
+
+/// Column a WHERE term constrains against constants, and the lower and upper bound it puts on it.
+fn column_bounds(term: &Expr) -> Option<(&str, Option<KeyBound>, Option<KeyBound>)> {
+    let bound = |value: &Literal, inclusive| Some(KeyBound { key: vec![value.clone()], inclusive });
+    match term {
+        Expr::Compare { op, left, right } => {
+            let (column, value, op) = match (left.as_ref(), right.as_ref()) {
+                (Expr::Literal(Literal::Ident(column)), Expr::Literal(value)) if !matches!(value, Literal::Ident(_)) => (column, value, *op),
+                (Expr::Literal(value), Expr::Literal(Literal::Ident(column))) if !matches!(value, Literal::Ident(_)) => (column, value, op.flip()),
+                _ => return None,
+            };
+            match op {
+                CompareOp::Eq => Some((column, bound(value, true), bound(value, true))),
+                CompareOp::Gt => Some((column, bound(value, false), None)),
+                CompareOp::Ge => Some((column, bound(value, true), None)),
+                CompareOp::Lt => Some((column, None, bound(value, false))),
+                CompareOp::Le => Some((column, None, bound(value, true))),
+                CompareOp::Ne => None,
+            }
+        },
+        Expr::Between { expr, low, high, negated: false } => match (expr.as_ref(), low.as_ref(), high.as_ref()) {
+            (Expr::Literal(Literal::Ident(column)), Expr::Literal(low), Expr::Literal(high))
+                if !matches!(low, Literal::Ident(_)) && !matches!(high, Literal::Ident(_)) => Some((column, bound(low, true), bound(high, true))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::{connection::Connection, sorter::DEFAULT_SORT_MEMORY, sql_parser::sql_parser, testing};
+
+    /// The access path picked for reading `items` with `condition`, like `items_a [5, _)`.
+    fn access(connection: &Connection, condition: &str) -> String {
+        let schema = connection.schema();
+        let where_expr = sql_parser::expression(condition).unwrap();
+        let bound = |bound: &Option<KeyBound>, open: &str, closed: &str| match bound {
+            Some(bound) => format!("{:?}{}", bound.key[0], if bound.inclusive { closed } else { open }),
+            None => "_".into(),
+        };
+        match choose_access(schema, schema.table("items").unwrap(), Some(&where_expr)) {
+            AccessPath::TableScan => "scan".into(),
+            AccessPath::Rowid(ranges) => format!("rowid {:?}", ranges),
+            AccessPath::Index { index, lower, upper } => format!("{} {} {}", index.name, bound(&lower, "<", "<="), bound(&upper, ">", ">=")),
+        }
+    }
+
+    /// Whether `items` is read already in the ORDER BY of `sql`, walked backwards or not.
+    fn read_order(connection: &Connection, sql: &str) -> Option<bool> {
+        let select = sql_parser::statement(sql).unwrap();
+        let table = connection.schema().table("items").unwrap();
+        let keys = sort_keys(&select.order_by, &[], &Scope::of_table(table, TextEncoding::Utf8)).unwrap();
+        open_table(connection.pager(), connection.schema(), table, select.where_expr.as_ref(), &keys, false).1
+    }
+
+    /// Whether the groups, or the DISTINCT rows, of `sql` on `items` come from reading the table in
+    /// group order.
+    fn streams_groups(connection: &Connection, sql: &str) -> bool {
+        let select = sql_parser::statement(sql).unwrap();
+        let table = connection.schema().table("items").unwrap();
+        let scope = Scope::of_table(table, TextEncoding::Utf8);
+        let terms = match select.distinct {
+            true => select.columns.iter().filter_map(|column| match column {
+                SelectItem::Expr { expr, .. } => Some(expr.clone()),
+                SelectItem::Star => None,
+            }).collect(),
+            false => group_terms(&select.group_by, &[], &scope).unwrap(),
+        };
+        let keys = grouping_keys(&terms, &scope);
+        open_table(connection.pager(), connection.schema(), table, select.where_expr.as_ref(), &keys, true).1.is_some()
+    }
+
+    #[test]
+    fn selects_quoted_table_and_columns() {
+        let connection = testing::open("types");
+        let sql = "SELECT \"order\", [x y], `a``b` FROM \"group\" WHERE [x y] > 'p'";
+        assert_eq!(testing::query(&connection, sql), ["2|two|20", "3|three|30"]);
+        assert_eq!(testing::query(&connection, "SELECT count(*) FROM 'group'"), ["3"]);
+    }
+
+    #[test]
+    fn joins_tables() {
+        let connection = testing::open("query");
+        let sql = "SELECT o.name, i.b, i.c FROM owners o JOIN items i ON i.id = o.item_id ORDER BY o.id";
+        assert_eq!(testing::query(&connection, sql), ["ann|APPLE|1.5", "bob|APPLE|1.5", "cid|cherry|null"]);
+        let sql = "SELECT name, count(*) FROM owners, items WHERE items.a = owners.id GROUP BY name ORDER BY 2 DESC, 1";
+        assert_eq!(testing::query(&connection, sql), ["ann|43", "bob|43", "cid|43", "dan|43", "eve|43"]);
+        let sql = "SELECT * FROM owners CROSS JOIN owners AS other WHERE owners.id = other.id - 4";
+        assert_eq!(testing::query(&connection, sql), ["1|3|ann|5|null|eve"]);
+        assert_eq!(testing::query(&connection, "SELECT a FROM owners o, items i WHERE i.id = 1").len(), 5);
+        assert_eq!(testing::query(&connection, "SELECT items.b FROM items WHERE items.id = 2"), ["cherry"]);
+        let error = |sql| connection.prepare(sql).err().unwrap().to_string();
+        assert_eq!(error("SELECT id FROM owners, items"), "ambiguous column name: id");
+        assert_eq!(error("SELECT owners.id FROM owners o"), "no such column: owners.id");
+    }
+
+    #[test]
+    fn index_bounds_take_the_column_affinity() {
+        let connection = testing::open("query");
+        assert_eq!(access(&connection, "a = 3"), "items_a IntegerLiteral(3)<= IntegerLiteral(3)>=");
+        assert_eq!(access(&connection, "a > '5'"), "items_a IntegerLiteral(5)< _");
+        assert_eq!(access(&connection, "a = '3.0'"), "items_a IntegerLiteral(3)<= IntegerLiteral(3)>=");
+        assert_eq!(access(&connection, "'5' < a AND a <= 6.5"), "items_a IntegerLiteral(5)< RealLiteral(6.5)>=");
+        assert_eq!(access(&connection, "c BETWEEN '1' AND 2"), "items_c_desc RealLiteral(1.0)<= RealLiteral(2.0)>=");
+        for (condition, count) in [("a > '5'", "43"), ("a > '5' AND a <= 6.5", "43"), ("c BETWEEN '1' AND 2", "3"), ("b = 'APPLE'", "150"), ("a = '3.0'", "43")] {
+            assert_eq!(testing::query(&connection, &format!("SELECT count(*) FROM items WHERE {}", condition)), [count], "{}", condition);
+        }
+    }
+
+    #[test]
+    fn chooses_the_access_path() {
+        let connection = testing::open("query");
+        // an equality beats a range on another index
+        assert_eq!(access(&connection, "a > 2 AND c = 1.5"), "items_c_desc RealLiteral(1.5)<= RealLiteral(1.5)>=");
+        assert_eq!(access(&connection, "c > 1 AND a = 3"), "items_a IntegerLiteral(3)<= IntegerLiteral(3)>=");
+        // the cursor compares keys as BINARY, so a NOCASE index is not seeked
+        assert_eq!(access(&connection, "b = 'APPLE'"), "scan");
+        assert_eq!(access(&connection, "c >= 149"), "items_c_desc RealLiteral(149.0)<= _");
+        assert_eq!(access(&connection, "d = 5"), "scan");
+        assert_eq!(access(&connection, "a > 2 OR a < 1"), "scan");
+        assert_eq!(access(&connection, "a + 0 = 3"), "scan");
+        // rows come in the order of the DESC index
+        assert_eq!(testing::query(&connection, "SELECT id FROM items WHERE c >= 149"), ["299", "298"]);
+    }
+
+    #[test]
+    fn seeks_rowid_ranges() {
+        let connection = testing::open("query");
+        assert_eq!(access(&connection, "id = 5"), "rowid [5..=5]");
+        assert_eq!(access(&connection, "id = '7'"), "rowid [7..=7]");
+        assert_eq!(access(&connection, "id IN (7, 3, 7)"), "rowid [3..=3, 7..=7]");
+        assert_eq!(access(&connection, "rowid > 10 AND rowid <= 20"), "rowid [11..=20]");
+        assert_eq!(access(&connection, "oid BETWEEN 4 AND 6 AND a > 1"), "rowid [4..=6]");
+        assert_eq!(access(&connection, "id < 2.5"), "rowid [-9223372036854775808..=2]");
+        assert_eq!(access(&connection, "id > 10 AND id < 5"), "rowid []");
+        assert_eq!(access(&connection, "id = 2.5"), "rowid []");
+        assert_eq!(access(&connection, "id = NULL"), "rowid []");
+        // a rowid equality beats an index equality, which beats a rowid range
+        assert_eq!(access(&connection, "id = 5 AND a = 5"), "rowid [5..=5]");
+        assert_eq!(access(&connection, "id > 10 AND a = 3"), "items_a IntegerLiteral(3)<= IntegerLiteral(3)>=");
+        assert_eq!(access(&connection, "id != 5"), "scan");
+
+        let ids = |condition: &str| testing::query(&connection, &format!("SELECT id FROM items WHERE {}", condition)).join(",");
+        assert_eq!(ids("id IN (7, 3, 7)"), "3,7");
+        assert_eq!(ids("id = '7'"), "7");
+        assert_eq!(ids("id < 2.5"), "1,2");
+        assert_eq!(ids("id > 10 AND id < 5"), "");
+        assert_eq!(ids("id >= 9223372036854775807 OR id < -5"), "");
+        assert_eq!(ids("id > 295 ORDER BY id DESC"), "300,299,298,297,296");
+    }
+
+    #[test]
+    fn orders_by_an_index_or_a_sort() {
+        let connection = testing::open("query");
+        let order = |sql: &str| read_order(&connection, &format!("SELECT * FROM items {}", sql));
+        assert_eq!(order("ORDER BY id"), Some(false));
+        assert_eq!(order("WHERE id > 5 ORDER BY rowid DESC"), Some(true));
+        assert_eq!(order("ORDER BY a"), Some(false));
+        assert_eq!(order("ORDER BY a DESC"), Some(true));
+        assert_eq!(order("ORDER BY a NULLS LAST"), None);
+        assert_eq!(order("ORDER BY c DESC"), Some(false));
+        assert_eq!(order("WHERE c > 3 ORDER BY c"), Some(true));
+        // the NOCASE index gives the NOCASE order of b, but not the BINARY one
+        assert_eq!(order("ORDER BY b"), Some(false));
+        assert_eq!(order("ORDER BY b COLLATE BINARY"), None);
+        assert_eq!(order("ORDER BY a, id"), None);
+        assert_eq!(order("WHERE a = 3 ORDER BY c"), None);
+    }
+
+    #[test]
+    fn orders_rows() {
+        let connection = testing::open("query");
+        let ids = |sql: &str| testing::query(&connection, &format!("SELECT id FROM items {}", sql)).join(",");
+        assert_eq!(ids("WHERE id < 12 ORDER BY c NULLS FIRST, id DESC"), "10,5,1,2,3,4,6,7,8,9,11");
+        assert_eq!(ids("WHERE id < 12 ORDER BY c DESC NULLS LAST"), "11,9,8,7,6,4,3,2,1,5,10");
+        assert_eq!(ids("WHERE id < 9 ORDER BY b, id DESC"), "8,7,4,3,5,1,6,2");
+        assert_eq!(ids("WHERE id < 9 ORDER BY b COLLATE BINARY DESC, id"), "2,6,4,8,1,5,3,7");
+        assert_eq!(ids("ORDER BY a DESC, c, id DESC LIMIT 5"), "300,265,230,195,160");
+        // NULLs, then numbers, then text
+        assert_eq!(testing::query(&connection, "SELECT d FROM items WHERE id < 7 ORDER BY d").join(","), "null,null,3,6,x1,x4");
+    }
+
+    #[test]
+    fn limits_and_offsets() {
+        let connection = testing::open("query");
+        let ids = |sql: &str| testing::query(&connection, &format!("SELECT id FROM items ORDER BY id {}", sql)).join(",");
+        assert_eq!(ids("LIMIT 2, 3"), "3,4,5");
+        assert_eq!(ids("LIMIT 1 + 1 OFFSET 2 * 2"), "5,6");
+        assert_eq!(ids("LIMIT -1 OFFSET 297"), "298,299,300");
+        assert_eq!(ids("LIMIT '2' OFFSET -3"), "1,2");
+        assert_eq!(ids("LIMIT 2.0 OFFSET 1"), "2,3");
+        assert_eq!(ids("LIMIT 0"), "");
+        let error = |sql| connection.prepare(sql).err().unwrap().to_string();
+        assert_eq!(error("SELECT id FROM items LIMIT 'x'"), "datatype mismatch");
+        assert_eq!(error("SELECT id FROM items LIMIT 1.5"), "datatype mismatch");
+    }
+
+    #[test]
+    fn groups_stream_through_an_index() {
+        let connection = testing::open("query");
+        let streams = |sql: &str| streams_groups(&connection, &format!("SELECT count(*) FROM items {}", sql));
+        assert!(streams("GROUP BY a"));
+        assert!(streams("WHERE a > 2 GROUP BY a"));
+        assert!(streams("GROUP BY c"));
+        assert!(streams("GROUP BY b"));
+        assert!(!streams("GROUP BY d"));
+        assert!(!streams("GROUP BY a % 2"));
+        assert!(!streams("WHERE c > 3 GROUP BY a"));
+    }
+
+    #[test]
+    fn groups_rows() {
+        let mut connection = testing::open("query");
+        for memory_budget in [DEFAULT_SORT_MEMORY, 0] {
+            connection.set_sort_memory_budget(memory_budget);
+            let rows = |sql: &str| testing::query(&connection, sql).join(" ");
+            assert_eq!(rows("SELECT a, count(*), sum(c) FROM items GROUP BY a HAVING count(*) > 42"), "1|43|2484.5 2|43|2593.5 3|43|2550.0 4|43|2656.5 5|43|2615.5 6|43|2569.5");
+            assert_eq!(rows("SELECT a % 2, count(*) FROM items GROUP BY 1"), "0|171 1|129");
+            assert_eq!(rows("SELECT count(*), min(id) FROM items GROUP BY b"), "150|3 75|1 75|2");
+            assert_eq!(rows("SELECT a, max(c) FROM items WHERE a > 4 GROUP BY a ORDER BY 2 DESC"), "5|149.5 6|146.5");
+            assert_eq!(rows("SELECT count(*) FROM items GROUP BY d IS NULL, typeof(d)"), "100 100 100");
+            assert_eq!(rows("SELECT a * 0, count(DISTINCT a) FROM items GROUP BY a * 0"), "0|7");
+        }
+        let error = connection.prepare("SELECT a FROM items GROUP BY 3").err().unwrap().to_string();
+        assert_eq!(error, "1st GROUP BY term out of range - should be between 1 and 1");
+    }
+
+    #[test]
+    fn distinct_streams_through_an_index() {
+        let connection = testing::open("query");
+        assert!(streams_groups(&connection, "SELECT DISTINCT a FROM items"));
+        assert!(streams_groups(&connection, "SELECT DISTINCT b FROM items"));
+        assert!(streams_groups(&connection, "SELECT DISTINCT c FROM items WHERE c > 100"));
+        assert!(!streams_groups(&connection, "SELECT DISTINCT d FROM items"));
+        assert!(!streams_groups(&connection, "SELECT DISTINCT a, d FROM items"));
+    }
+
+    #[test]
+    fn distinct_rows() {
+        let mut connection = testing::open("query");
+        for memory_budget in [DEFAULT_SORT_MEMORY, 0] {
+            connection.set_sort_memory_budget(memory_budget);
+            let rows = |sql: &str| testing::query(&connection, sql).join(" ");
+            assert_eq!(rows("SELECT DISTINCT a FROM items"), "0 1 2 3 4 5 6");
+            // NOCASE makes 'apple' and 'APPLE' the same value
+            assert_eq!(rows("SELECT DISTINCT b FROM items"), "APPLE Banana cherry");
+            assert_eq!(rows("SELECT DISTINCT d IS NULL, a % 2 FROM items ORDER BY 1, 2"), "0|0 0|1 1|0 1|1");
+            // NULLs are not distinct from each other
+            assert_eq!(rows("SELECT DISTINCT item_id > 5 FROM owners"), "0 1 null");
+            assert_eq!(rows("SELECT DISTINCT c FROM items WHERE id > 290 ORDER BY c DESC"), "149.5 149.0 148.5 148.0 147.0 146.5 146.0 145.5 null");
+            assert_eq!(rows("SELECT DISTINCT a FROM items ORDER BY a DESC LIMIT 3"), "6 5 4");
+        }
+    }
+}
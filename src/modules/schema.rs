@@ -0,0 +1,116 @@
+use anyhow::{Result, anyhow, bail};
+
+use crate::modules::{ast::Literal, btree::BTreeCursor, index::Index, pager::Pager, sql_parser::sql_parser, table::Table};
+
+/// A row of sqlite_schema.
+pub struct SchemaEntry {
+    pub schema_type: String,
+    pub name: String,
+    pub sql: Option<String>,
+}
+
+/// Every object described by the sqlite_schema table.
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub indices: Vec<Index>,
+    pub entries: Vec<SchemaEntry>,
+    /// Tables whose CREATE TABLE statement we cannot parse, with the parse error
+    pub unparsed: Vec<(String, String)>,
+}
+
+impl Schema {
+    /// Reads sqlite_schema, which is a table b-tree rooted at page 1.
+    pub fn load(pager: &Pager) -> Result<Self> {
+        let mut schema = Self { tables: vec![], indices: vec![], entries: vec![], unparsed: vec![] };
+        let mut cursor = BTreeCursor::table(pager, 1);
+        let mut valid = cursor.first()?;
+        while valid {
+            let record = cursor.record()?;
+            valid = cursor.next()?;
+            // sqlite_schema columns: type, name, tbl_name, rootpage, sql
+            let [Literal::StringLiteral(schema_type), Literal::StringLiteral(name), Literal::StringLiteral(_), rootpage, sql] = &record[..] else {
+                bail!("Malformed sqlite_schema record")
+            };
+            let rootpage = match rootpage {
+                Literal::IntegerLiteral(n) => *n as u32,
+                _ => 0,
+            };
+            let Literal::StringLiteral(sql) = sql else {
+                schema.entries.push(SchemaEntry { schema_type: schema_type.clone(), name: name.clone(), sql: None });
+                continue; // automatic indexes have no sql
+            };
+            schema.entries.push(SchemaEntry { schema_type: schema_type.clone(), name: name.clone(), sql: Some(sql.clone()) });
+            match schema_type.as_str() {
+                "table" => {
+                    if rootpage == 0 {
+                        continue; // virtual tables have no b-tree
+                    }
+                    // tables we cannot parse only fail the queries that use them, see [`Schema::table`]
+                    match sql_parser::create_table(sql) {
+                        Ok(definition) => schema.tables.push(Table::from_definition(definition, rootpage)),
+                        Err(error) => schema.unparsed.push((name.clone(), error.to_string())),
+                    }
+                },
+                "index" => {
+                    // indexes we cannot parse are left out, the planner falls back to scanning the table
+                    if let Ok(definition) = sql_parser::create_index(sql) {
+                        schema.indices.push(Index::from_definition(definition, rootpage));
+                    }
+                },
+                "view" | "trigger" => {},
+                _ => bail!("Unrecognized schema type: {}", schema_type),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// The table called `name`, or why we cannot read it.
+    pub fn table(&self, name: &str) -> Result<&Table> {
+        if let Some(table) = self.tables.iter().find(|table| table.name.eq_ignore_ascii_case(name)) {
+            return Ok(table);
+        }
+        match self.unparsed.iter().find(|(table, _)| table.eq_ignore_ascii_case(name)) {
+            Some((table, error)) => Err(anyhow!("Cannot parse the schema of table {}: {}", table, error)),
+            None => Err(anyhow!("Table not found")),
+        }
+    }
+
+    /// Number of sqlite_schema rows of the given type, including virtual tables and automatic indexes.
+    pub fn count(&self, schema_type: &str) -> usize {
+        self.entries.iter().filter(|entry| entry.schema_type == schema_type).count()
+    }
+
+    /// Total length in characters of the SQL text stored in sqlite_schema.
+    pub fn sql_size(&self) -> usize {
+        self.entries.iter().filter_map(|entry| entry.sql.as_ref()).map(|sql| sql.chars().count()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::testing;
+
+    #[test]
+    fn unparsable_tables_fail_only_their_queries() {
+        let connection = testing::open("broken_schema");
+        assert_eq!(testing::query(&connection, "SELECT a FROM good"), ["1"]);
+        let error = connection.prepare("SELECT a FROM broken").err().unwrap().to_string();
+        assert!(error.starts_with("Cannot parse the schema of table broken: "), "{}", error);
+        assert_eq!(connection.prepare("SELECT a FROM missing").err().unwrap().to_string(), "Table not found");
+    }
+
+    #[test]
+    fn loads_a_schema_spanning_several_pages() {
+        let connection = testing::open("schema");
+        // an interior page at the root of sqlite_schema
+        assert_eq!(connection.pager().read_page(1).unwrap()[100], 0x05);
+        let schema = connection.schema();
+        let counts = ["table", "index", "view", "trigger"].map(|schema_type| schema.count(schema_type));
+        assert_eq!(counts, [30, 4, 1, 1]);
+        assert_eq!(schema.sql_size(), 1774);
+        assert_eq!(schema.tables.len(), 30);
+        assert_eq!(schema.indices.len(), 3);
+        assert_eq!(testing::query(&connection, "SELECT name FROM t30 ORDER BY id"), ["last", "table"]);
+        assert_eq!(testing::query(&connection, "SELECT count(*) FROM t01 WHERE name = 'last'"), ["1"]);
+    }
+}
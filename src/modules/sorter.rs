@@ -0,0 +1,284 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+};
+
+use anyhow::Result;
+
+use crate::modules::{ast::Literal, eval::Scope, executor::SortKey, header::TextEncoding, helpers::{encode_record, parse_record}};
+
+/// Memory a sort may use for buffered rows before it spills them to disk.
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Most runs merged at once, more are first merged into longer runs.
+const MERGE_WIDTH: usize = 16;
+
+/// A row with the values of its sort keys in front.
+type Entry = (Vec<Literal>, Vec<Literal>);
+
+/// Sorts rows that may not fit in memory. Rows are buffered until they reach the memory budget,
+/// then the buffer is sorted and written to a temporary file as a run of records. At the end the
+/// runs are merged. Rows with equal keys keep the order they were pushed in.
+pub struct Sorter {
+    keys: Vec<SortKey>,
+    scope: Scope,
+    memory_budget: usize,
+    buffer: Vec<Entry>,
+    buffer_size: usize,
+    runs: Vec<Run>,
+}
+
+impl Sorter {
+    pub fn new(keys: Vec<SortKey>, scope: Scope, memory_budget: usize) -> Self {
+        Self { keys, scope, memory_budget, buffer: vec![], buffer_size: 0, runs: vec![] }
+    }
+
+    pub fn push(&mut self, key: Vec<Literal>, row: Vec<Literal>) -> Result<()> {
+        self.buffer_size += row_size(&key) + row_size(&row);
+        self.buffer.push((key, row));
+        if self.buffer_size > self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts what is left and returns the rows in order.
+    pub fn finish(mut self) -> Result<SortedRows> {
+        self.sort_buffer();
+        if self.runs.is_empty() {
+            return Ok(SortedRows::Memory(mem::take(&mut self.buffer).into_iter()));
+        }
+        self.spill()?;
+        while self.runs.len() > MERGE_WIDTH {
+            let runs = self.runs.drain(..MERGE_WIDTH).collect();
+            let mut merge = Merge::new(runs, self.keys.clone(), self.scope.clone())?;
+            let mut run = RunWriter::create()?;
+            while let Some(entry) = merge.next_entry()? {
+                run.write(&entry)?;
+            }
+            // the merged rows came first, so they stay in front of the later runs for ties
+            self.runs.insert(0, run.finish()?);
+        }
+        Ok(SortedRows::Merge(Merge::new(mem::take(&mut self.runs), self.keys, self.scope)?))
+    }
+
+    fn sort_buffer(&mut self) {
+        let (keys, scope) = (&self.keys, &self.scope);
+        self.buffer.sort_by(|(a, _), (b, _)| compare_keys(keys, scope, a, b));
+    }
+
+    /// Writes the buffered rows to a new run.
+    fn spill(&mut self) -> Result<()> {
+        self.sort_buffer();
+        let mut run = RunWriter::create()?;
+        for entry in self.buffer.drain(..) {
+            run.write(&entry)?;
+        }
+        self.runs.push(run.finish()?);
+        self.buffer_size = 0;
+        Ok(())
+    }
+}
+
+/// Output of a [`Sorter`].
+pub enum SortedRows {
+    Memory(std::vec::IntoIter<Entry>),
+    Merge(Merge),
+}
+
+impl SortedRows {
+    pub fn next_row(&mut self) -> Result<Option<Vec<Literal>>> {
+        match self {
+            Self::Memory(rows) => Ok(rows.next().map(|(_, row)| row)),
+            Self::Merge(merge) => Ok(merge.next_entry()?.map(|(_, row)| row)),
+        }
+    }
+}
+
+fn compare_keys(keys: &[SortKey], scope: &Scope, a: &[Literal], b: &[Literal]) -> Ordering {
+    for ((a, b), key) in a.iter().zip(b).zip(keys) {
+        let ordering = key.compare(scope, a, b);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Rough heap and inline size of a row.
+pub(crate) fn row_size(row: &[Literal]) -> usize {
+    row.iter().map(value_size).sum::<usize>() + mem::size_of::<Vec<Literal>>()
+}
+
+/// Rough heap and inline size of a value.
+fn value_size(value: &Literal) -> usize {
+    let heap = match value {
+        Literal::Ident(s) | Literal::StringLiteral(s) => s.len(),
+        Literal::BlobLiteral(b) => b.len(),
+        _ => 0,
+    };
+    mem::size_of::<Literal>() + heap
+}
+
+/// K-way merge of sorted runs, taking the first run's row on ties to keep the sort stable.
+pub struct Merge {
+    runs: Vec<Run>,
+    /// Next entry of each run
+    heads: Vec<Option<Entry>>,
+    keys: Vec<SortKey>,
+    scope: Scope,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>, keys: Vec<SortKey>, scope: Scope) -> Result<Self> {
+        let heads = runs.iter_mut().map(|run| run.read(keys.len())).collect::<Result<_>>()?;
+        Ok(Self { runs, heads, keys, scope })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Entry>> {
+        let mut smallest: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some((key, _)) = head else {
+                continue;
+            };
+            let smaller = smallest.map_or(true, |j| {
+                let (smallest_key, _) = self.heads[j].as_ref().unwrap();
+                compare_keys(&self.keys, &self.scope, key, smallest_key) == Ordering::Less
+            });
+            if smaller {
+                smallest = Some(i);
+            }
+        }
+        let Some(i) = smallest else {
+            return Ok(None);
+        };
+        let next = self.runs[i].read(self.keys.len())?;
+        Ok(mem::replace(&mut self.heads[i], next))
+    }
+}
+
+/// A run being written: each entry is a u32 length followed by a record of its keys and row.
+struct RunWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl RunWriter {
+    fn create() -> Result<Self> {
+        static NEXT_RUN: AtomicU64 = AtomicU64::new(0);
+        let name = format!("codecrafters-sqlite-sort-{}-{}", process::id(), NEXT_RUN.fetch_add(1, AtomicOrdering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok(Self { path, file: BufWriter::new(file) })
+    }
+
+    fn write(&mut self, (key, row): &Entry) -> Result<()> {
+        let values = key.iter().chain(row).cloned().collect::<Vec<_>>();
+        let record = encode_record(&values);
+        self.file.write_all(&(record.len() as u32).to_be_bytes())?;
+        self.file.write_all(&record)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Run> {
+        self.file.flush()?;
+        let file = BufReader::new(File::open(&self.path)?);
+        Ok(Run { path: mem::take(&mut self.path), file })
+    }
+}
+
+impl Drop for RunWriter {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A sorted run on disk, deleted once dropped.
+struct Run {
+    path: PathBuf,
+    file: BufReader<File>,
+}
+
+impl Run {
+    fn read(&mut self, key_count: usize) -> Result<Option<Entry>> {
+        let mut length = [0; 4];
+        match self.file.read_exact(&mut length) {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let mut record = vec![0; u32::from_be_bytes(length) as usize];
+        self.file.read_exact(&mut record)?;
+        let mut key = parse_record(&record, TextEncoding::Utf8)?;
+        let row = key.split_off(key_count);
+        Ok(Some((key, row)))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::{ast::{Expr, SortOrder}, eval::Collation};
+
+    fn sorted(rows: &[(i64, i64)], order: SortOrder, memory_budget: usize) -> Vec<(i64, i64)> {
+        let key = SortKey { expr: Expr::Literal(Literal::Ident("k".into())), order, nulls_first: true, collation: Collation::Binary };
+        let scope = Scope::new(vec!["k".into(), "v".into()], TextEncoding::Utf8);
+        let mut sorter = Sorter::new(vec![key], scope, memory_budget);
+        for &(k, v) in rows {
+            sorter.push(vec![Literal::IntegerLiteral(k)], vec![Literal::IntegerLiteral(k), Literal::IntegerLiteral(v)]).unwrap();
+        }
+        let mut rows = sorter.finish().unwrap();
+        let mut out = vec![];
+        while let Some(row) = rows.next_row().unwrap() {
+            let [Literal::IntegerLiteral(k), Literal::IntegerLiteral(v)] = row[..] else {
+                panic!("unexpected row {:?}", row);
+            };
+            out.push((k, v));
+        }
+        out
+    }
+
+    /// Rows with only a few distinct keys, numbered in push order.
+    fn tied_rows(count: i64) -> Vec<(i64, i64)> {
+        (0..count).map(|v| ((v * 7) % 3, v)).collect()
+    }
+
+    fn stable_sort(mut rows: Vec<(i64, i64)>, order: SortOrder) -> Vec<(i64, i64)> {
+        rows.sort_by(|a, b| if order == SortOrder::Desc { b.0.cmp(&a.0) } else { a.0.cmp(&b.0) });
+        rows
+    }
+
+    #[test]
+    fn sorts_in_memory() {
+        let rows = tied_rows(100);
+        assert_eq!(sorted(&rows, SortOrder::Asc, DEFAULT_SORT_MEMORY), stable_sort(rows, SortOrder::Asc));
+    }
+
+    #[test]
+    fn spilled_ties_keep_push_order_across_many_runs() {
+        // a budget of nothing spills a run per row, well past MERGE_WIDTH of them
+        for count in [MERGE_WIDTH as i64 + 1, 3 * MERGE_WIDTH as i64 + 5, 300] {
+            let rows = tied_rows(count);
+            assert_eq!(sorted(&rows, SortOrder::Asc, 0), stable_sort(rows.clone(), SortOrder::Asc));
+            assert_eq!(sorted(&rows, SortOrder::Desc, 0), stable_sort(rows, SortOrder::Desc));
+        }
+    }
+
+    #[test]
+    fn spilled_runs_with_several_rows() {
+        let rows = tied_rows(2000);
+        assert_eq!(sorted(&rows, SortOrder::Asc, 1024), stable_sort(rows, SortOrder::Asc));
+    }
+}
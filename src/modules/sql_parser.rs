@@ -1,4 +1,4 @@
-use crate::modules::ast::{Expr, Literal, SelectItem, SelectStatement};
+use crate::modules::ast::{AggregateCall, AggregateFunction, BinaryOp, ColumnConstraint, ColumnDefinition, CompareOp, CreateIndexStatement, CreateTableStatement, DefaultValue, Expr, ForeignKey, FunctionCall, IndexKey, IndexedColumn, Join, Literal, OrderingTerm, SelectItem, SelectStatement, SortOrder, TableConstraint, UnaryOp};
 
 peg::parser! {
     pub grammar sql_parser() for str {
@@ -6,22 +6,75 @@ peg::parser! {
         pub rule statement() -> SelectStatement
             = _ s:select_stmt() _ ";"? _ { s }
 
+        pub rule create_table() -> CreateTableStatement
+            = _ c:create_table_stmt() _ ";"? _ { c }
+
+        pub rule create_index() -> CreateIndexStatement
+            = _ c:create_index_stmt() _ ";"? _ { c }
+
+        pub rule expression() -> Expr
+            = _ e:expr() _ { e }
+
         // ---- SELECT ----
         rule select_stmt() -> SelectStatement
-            = kw_select() _ cols:select_list() _ kw_from() _ table:ident() _ where_clause:where_clause()? {
+            = kw("SELECT") _ distinct:(d:(kw("DISTINCT") { true } / kw("ALL") { false }) _ {d})? cols:select_list() _ kw("FROM") _ from:from_clause() _ where_clause:where_clause()?
+              group_by:(_ g:group_by() {g})? having:(_ kw("HAVING") _ h:expr() {h})? order_by:(_ o:order_by() {o})?
+              limit:(_ l:limit() {l})? {
+                let (limit, offset) = limit.unzip();
+                let (table, table_alias, joins) = from;
                 SelectStatement {
+                    distinct: distinct.unwrap_or(false),
                     columns: cols,
                     table,
-                    where_expr: where_clause
+                    table_alias,
+                    joins,
+                    where_expr: where_clause,
+                    group_by: group_by.unwrap_or_default(),
+                    having,
+                    order_by: order_by.unwrap_or_default(),
+                    limit,
+                    offset: offset.flatten(),
                 }
             }
 
+        /// The first table and its alias, then the tables joined to it
+        rule from_clause() -> (String, Option<String>, Vec<Join>)
+            = table:name() alias:(_ a:table_alias() {a})? joins:(_ j:join() {j})* { (table, alias, joins) }
+
+        rule join() -> Join
+            = ("," / ((kw("INNER") / kw("CROSS")) _)? kw("JOIN")) _ table:name() alias:(_ a:table_alias() {a})?
+              constraint:(_ kw("ON") _ e:expr() {e})? {
+                Join { table, alias, constraint }
+            }
+
+        /// `AS name` after a table, where AS may be left out
+        rule table_alias() -> String
+            = kw("AS") _ a:name() { a }
+            / !(kw("WHERE") / kw("GROUP") / kw("HAVING") / kw("ORDER") / kw("LIMIT") / kw("JOIN") / kw("INNER") / kw("CROSS")
+                / kw("LEFT") / kw("NATURAL") / kw("ON")) a:name() { a }
+
         rule where_clause() -> Expr
-            = kw_where() _ e:expr() {e}
+            = kw("WHERE") _ e:expr() {e}
+
+        rule group_by() -> Vec<Expr>
+            = kw("GROUP") _ kw("BY") _ terms:(expr() ++ (_ "," _)) { terms }
+
+        rule order_by() -> Vec<OrderingTerm>
+            = kw("ORDER") _ kw("BY") _ terms:(ordering_term() ++ (_ "," _)) { terms }
+
+        /// The limit and offset, `LIMIT x, y` skips x rows and returns y
+        rule limit() -> (Expr, Option<Expr>)
+            = kw("LIMIT") _ offset:expr() _ "," _ limit:expr() { (limit, Some(offset)) }
+            / kw("LIMIT") _ limit:expr() offset:(_ kw("OFFSET") _ o:expr() {o})? { (limit, offset) }
+
+        rule ordering_term() -> OrderingTerm
+            = expr:expr() collation:(_ kw("COLLATE") _ n:name() {n})? order:(_ o:sort_order() {o})?
+              nulls_first:(_ kw("NULLS") _ f:(kw("FIRST") { true } / kw("LAST") { false }) {f})? {
+                OrderingTerm { expr, collation, order: order.unwrap_or(SortOrder::Asc), nulls_first }
+            }
 
         rule select_list() -> Vec<SelectItem>
             = "*" { vec![SelectItem::Star] }
-            / kw_count() "(*)" { vec![SelectItem::Count] }
             / head:select_item() tail:(_ "," _ item:select_item() {item})* {
                 let mut v = Vec::new();
                 v.push(head);
@@ -32,17 +85,252 @@ peg::parser! {
             }
 
         rule select_item() -> SelectItem
-            = l:literal() { SelectItem::Literal(l) }
+            = sql:source() start:position!() expr:expr() end:position!() alias:(_ a:alias() {a})? {
+                let name = alias.clone().unwrap_or_else(|| sql[start..end].to_string());
+                SelectItem::Expr { expr, name, alias }
+            }
+
+        /// `AS name`, where AS may be left out
+        rule alias() -> String
+            = kw("AS") _ a:name() { a }
+            / !kw("FROM") a:name() { a }
+
+        /// The whole statement, for slicing out the text of what a rule matched
+        rule source() -> &'input str
+            = #{|input, pos| peg::RuleResult::Matched(pos, input)}
 
         // ---- Expressions ----
-        rule expr() -> Expr
-            = col:literal() _ "=" _ con:literal() { Expr::Equality { column: col, condition: con } }
+        /// Boolean expression, from loosest to tightest: OR, AND, NOT, equality-like tests, ordering, values
+        rule expr() -> Expr = precedence!{
+            x:(@) _ kw("OR") _ y:@ { Expr::Or(Box::new(x), Box::new(y)) }
+            --
+            x:(@) _ kw("AND") _ y:@ { Expr::And(Box::new(x), Box::new(y)) }
+            --
+            kw("NOT") _ x:@ { Expr::Not(Box::new(x)) }
+            --
+            x:(@) _ ("==" / "=") _ y:@ { compare(CompareOp::Eq, x, y) }
+            x:(@) _ ("!=" / "<>") _ y:@ { compare(CompareOp::Ne, x, y) }
+            x:(@) _ kw("IS") _ kw("NOT") _ y:@ { Expr::Is { left: Box::new(x), right: Box::new(y), negated: true } }
+            x:(@) _ kw("IS") _ y:@ { Expr::Is { left: Box::new(x), right: Box::new(y), negated: false } }
+            x:(@) _ negated:not() kw("BETWEEN") _ low:value() _ kw("AND") _ high:value() {
+                Expr::Between { expr: Box::new(x), low: Box::new(low), high: Box::new(high), negated }
+            }
+            x:(@) _ negated:not() kw("IN") _ "(" _ list:(expr() ** (_ "," _)) _ ")" {
+                Expr::In { expr: Box::new(x), list, negated }
+            }
+            x:(@) _ kw("ISNULL") { is_null(x, false) }
+            x:(@) _ (kw("NOTNULL") / kw("NOT") _ kw("NULL")) { is_null(x, true) }
+            --
+            x:(@) _ "<=" _ y:@ { compare(CompareOp::Le, x, y) }
+            x:(@) _ ">=" _ y:@ { compare(CompareOp::Ge, x, y) }
+            x:(@) _ "<" _ y:@ { compare(CompareOp::Lt, x, y) }
+            x:(@) _ ">" _ y:@ { compare(CompareOp::Gt, x, y) }
+            --
+            e:value() { e }
+        }
+
+        /// Value expression, from loosest to tightest: bitwise operators, addition and subtraction,
+        /// multiplication and division, concatenation, unary operators
+        rule value() -> Expr = precedence!{
+            x:(@) _ "&" _ y:@ { binary(BinaryOp::BitAnd, x, y) }
+            x:(@) _ "|" !"|" _ y:@ { binary(BinaryOp::BitOr, x, y) }
+            x:(@) _ "<<" _ y:@ { binary(BinaryOp::ShiftLeft, x, y) }
+            x:(@) _ ">>" _ y:@ { binary(BinaryOp::ShiftRight, x, y) }
+            --
+            x:(@) _ "+" _ y:@ { binary(BinaryOp::Add, x, y) }
+            x:(@) _ "-" _ y:@ { binary(BinaryOp::Subtract, x, y) }
+            --
+            x:(@) _ "*" _ y:@ { binary(BinaryOp::Multiply, x, y) }
+            x:(@) _ "/" _ y:@ { binary(BinaryOp::Divide, x, y) }
+            x:(@) _ "%" _ y:@ { binary(BinaryOp::Remainder, x, y) }
+            --
+            x:(@) _ "||" _ y:@ { binary(BinaryOp::Concat, x, y) }
+            --
+            "-" _ x:@ { negate(x) }
+            "+" _ x:@ { Expr::Unary { op: UnaryOp::Plus, expr: Box::new(x) } }
+            "~" _ x:@ { Expr::Unary { op: UnaryOp::BitNot, expr: Box::new(x) } }
+            --
+            e:operand() { e }
+        }
+
+        /// A single value: a parenthesised expression, a function call, a literal or a column
+        rule operand() -> Expr
+            = "(" _ e:expr() _ ")" { e }
+            / c:aggregate_call() { Expr::Aggregate(c) }
+            / c:function_call() { Expr::Function(c) }
             / l:literal() { Expr::Literal(l) }
 
+        /// `count(*)`, or an aggregate function applied to expressions, arity is checked when planning
+        rule aggregate_call() -> AggregateCall
+            = function:aggregate_function() _ "(" _ "*" _ ")" {? if function == AggregateFunction::Count { Ok(AggregateCall { function, args: vec![], distinct: false }) } else { Err("expression") } }
+            / function:aggregate_function() _ "(" _ distinct:(kw("DISTINCT") _)? args:(expr() ** (_ "," _)) _ ")" {?
+                // min() and max() of several values are the scalar functions
+                if matches!(function, AggregateFunction::Min | AggregateFunction::Max) && args.len() != 1 {
+                    return Err("aggregate");
+                }
+                Ok(AggregateCall { function, args, distinct: distinct.is_some() })
+            }
+
+        /// A scalar function applied to expressions, the name and arity are checked when planning
+        rule function_call() -> FunctionCall
+            = name:ident() _ "(" _ args:(expr() ** (_ "," _)) _ ")" { FunctionCall { name, args } }
+
+        rule aggregate_function() -> AggregateFunction
+            = n:$(['a'..='z' | 'A'..='Z' | '_']+) {? AggregateFunction::from_name(n).ok_or("function") }
+
+        /// Whether an optional NOT precedes BETWEEN or IN
+        rule not() -> bool
+            = n:(kw("NOT") _)? { n.is_some() }
+
+        // ---- CREATE TABLE ----
+        rule create_table_stmt() -> CreateTableStatement
+            = kw("CREATE") _ (kw("TEMP") / kw("TEMPORARY"))? _ kw("TABLE") _ (kw("IF") _ kw("NOT") _ kw("EXISTS") _)?
+              (name() _ "." _)? name:name() _ "(" _
+              columns:(column_definition() ++ (_ "," _))
+              constraints:(_ "," _ c:table_constraint() {c})* _ ")"
+              options:(_ o:(table_option() ++ (_ "," _)) {o})? {
+                CreateTableStatement {
+                    name,
+                    columns,
+                    constraints,
+                    without_rowid: options.unwrap_or_default().contains(&true),
+                }
+            }
+
+        // ---- CREATE INDEX ----
+        rule create_index_stmt() -> CreateIndexStatement
+            = kw("CREATE") _ unique:(kw("UNIQUE") _)? kw("INDEX") _ (kw("IF") _ kw("NOT") _ kw("EXISTS") _)?
+              (name() _ "." _)? name:name() _ kw("ON") _ table:name() _ "(" _
+              columns:(indexed_column() ++ (_ "," _)) _ ")"
+              where_expr:(_ kw("WHERE") _ e:expr() {e})? {
+                CreateIndexStatement {
+                    name,
+                    table,
+                    unique: unique.is_some(),
+                    columns,
+                    where_expr,
+                }
+            }
+
+        rule indexed_column() -> IndexedColumn
+            = key:index_key() collation:(_ kw("COLLATE") _ n:name() {n})? order:(_ o:sort_order() {o})? {
+                IndexedColumn { key, collation, order: order.unwrap_or(SortOrder::Asc) }
+            }
+
+        rule index_key() -> IndexKey
+            = n:name() &(_ ("," / ")" / kw("COLLATE") / kw("ASC") / kw("DESC"))) { IndexKey::Column(n) }
+            / e:$(index_expression()) { IndexKey::Expression(e.trim().to_string()) }
+
+        rule index_expression()
+            = ("(" balanced() ")" / string_literal() / quoted_name() / !(kw("COLLATE") / kw("ASC") / kw("DESC")) ident() / [^ '(' | ')' | ',' | '\'' | '"' | 'a'..='z' | 'A'..='Z' | '_'])+
+
+        /// true for WITHOUT ROWID, false for STRICT
+        rule table_option() -> bool
+            = kw("WITHOUT") _ kw("ROWID") { true }
+            / kw("STRICT") { false }
+
+        rule column_definition() -> ColumnDefinition
+            = !table_constraint_start() name:name() type_name:(_ t:type_name() {t})? constraints:(_ c:column_constraint() {c})* {
+                ColumnDefinition {
+                    name,
+                    type_name,
+                    constraints: constraints.into_iter().flatten().collect(),
+                }
+            }
+
+        /// Declared type such as `INTEGER`, `UNSIGNED BIG INT` or `DECIMAL(10, 2)`
+        rule type_name() -> String
+            = t:$((!constraint_start() ident()) ++ _ (_ "(" _ signed_number() _ ("," _ signed_number() _)? ")")?) { t.to_string() }
+
+        /// Column constraints, `None` for the no-op NULL constraint
+        rule column_constraint() -> Option<ColumnConstraint>
+            = (kw("CONSTRAINT") _ name() _)? c:(
+                kw("PRIMARY") _ kw("KEY") order:(_ o:sort_order() {o})? (_ conflict_clause())? autoincrement:(_ kw("AUTOINCREMENT"))? {
+                    Some(ColumnConstraint::PrimaryKey { order, autoincrement: autoincrement.is_some() })
+                }
+                / kw("NOT") _ kw("NULL") (_ conflict_clause())? { Some(ColumnConstraint::NotNull) }
+                / kw("NULL") (_ conflict_clause())? { None }
+                / kw("UNIQUE") (_ conflict_clause())? { Some(ColumnConstraint::Unique) }
+                / kw("CHECK") _ "(" e:$(balanced()) ")" { Some(ColumnConstraint::Check(e.trim().to_string())) }
+                / kw("DEFAULT") _ d:default_value() { Some(ColumnConstraint::Default(d)) }
+                / kw("COLLATE") _ n:name() { Some(ColumnConstraint::Collate(n)) }
+                / f:foreign_key_clause() { Some(ColumnConstraint::References(f)) }
+                / (kw("GENERATED") _ kw("ALWAYS") _)? kw("AS") _ "(" e:$(balanced()) ")" stored:(_ s:(kw("STORED") { true } / kw("VIRTUAL") { false }) {s})? {
+                    Some(ColumnConstraint::Generated { expr: e.trim().to_string(), stored: stored.unwrap_or(false) })
+                }
+            ) { c }
+
+        rule default_value() -> DefaultValue
+            = "(" e:$(balanced()) ")" { DefaultValue::Expression(e.trim().to_string()) }
+            / n:signed_number() { DefaultValue::Literal(n) }
+            / s:string_literal() { DefaultValue::Literal(Literal::StringLiteral(s)) }
+            / b:blob_literal() { DefaultValue::Literal(Literal::BlobLiteral(b)) }
+            / kw("NULL") { DefaultValue::Literal(Literal::Null) }
+            / kw("TRUE") { DefaultValue::Literal(Literal::IntegerLiteral(1)) }
+            / kw("FALSE") { DefaultValue::Literal(Literal::IntegerLiteral(0)) }
+            / e:$(kw("CURRENT_TIMESTAMP") / kw("CURRENT_TIME") / kw("CURRENT_DATE")) { DefaultValue::Expression(e.to_uppercase()) }
+            / n:name() { DefaultValue::Literal(Literal::StringLiteral(n)) }
+
+        rule table_constraint() -> TableConstraint
+            = (kw("CONSTRAINT") _ name() _)? c:(
+                kw("PRIMARY") _ kw("KEY") _ "(" _ cols:indexed_column_names() _ ")" (_ conflict_clause())? { TableConstraint::PrimaryKey(cols) }
+                / kw("UNIQUE") _ "(" _ cols:indexed_column_names() _ ")" (_ conflict_clause())? { TableConstraint::Unique(cols) }
+                / kw("CHECK") _ "(" e:$(balanced()) ")" { TableConstraint::Check(e.trim().to_string()) }
+                / kw("FOREIGN") _ kw("KEY") _ "(" _ columns:name_list() _ ")" _ references:foreign_key_clause() {
+                    TableConstraint::ForeignKey { columns, references }
+                }
+            ) { c }
+
+        rule foreign_key_clause() -> ForeignKey
+            = kw("REFERENCES") _ table:name() columns:(_ "(" _ c:name_list() _ ")" {c})?
+              (_ (kw("ON") _ (kw("DELETE") / kw("UPDATE")) _ foreign_key_action() / kw("MATCH") _ name()))*
+              (_ kw("NOT"))? (_ kw("DEFERRABLE") (_ kw("INITIALLY") _ (kw("DEFERRED") / kw("IMMEDIATE")))?)? {
+                ForeignKey { table, columns: columns.unwrap_or_default() }
+            }
+
+        rule foreign_key_action()
+            = kw("SET") _ (kw("NULL") / kw("DEFAULT")) / kw("CASCADE") / kw("RESTRICT") / kw("NO") _ kw("ACTION")
+
+        rule conflict_clause()
+            = kw("ON") _ kw("CONFLICT") _ (kw("ROLLBACK") / kw("ABORT") / kw("FAIL") / kw("IGNORE") / kw("REPLACE"))
+
+        /// Column names of a table PRIMARY KEY/UNIQUE constraint, dropping collations and sort orders
+        rule indexed_column_names() -> Vec<String>
+            = (n:name() (_ kw("COLLATE") _ name())? (_ sort_order())? {n}) ++ (_ "," _)
+
+        rule name_list() -> Vec<String>
+            = name() ++ (_ "," _)
+
+        rule sort_order() -> SortOrder
+            = kw("ASC") { SortOrder::Asc }
+            / kw("DESC") { SortOrder::Desc }
+
+        rule signed_number() -> Literal
+            = sign:$(['+' | '-'])? _ n:number_literal() {
+                match sign {
+                    Some("-") => negate_number(n),
+                    _ => n,
+                }
+            }
+
+        rule constraint_start()
+            = kw("CONSTRAINT") / kw("PRIMARY") / kw("NOT") / kw("NULL") / kw("UNIQUE") / kw("CHECK")
+            / kw("DEFAULT") / kw("COLLATE") / kw("REFERENCES") / kw("GENERATED") / kw("AS")
+
+        rule table_constraint_start()
+            = kw("CONSTRAINT") / kw("PRIMARY") / kw("UNIQUE") / kw("CHECK") / kw("FOREIGN")
+
+        /// Any text with balanced parentheses, used for expressions kept as SQL text
+        rule balanced()
+            = ("(" balanced() ")" / string_literal() / quoted_name() / [^ '(' | ')' | '\'' | '"'])*
+
         rule literal() -> Literal
-            = id:ident() { Literal::Ident(id) }
+            = b:blob_literal() { Literal::BlobLiteral(b) }
+            / kw("NULL") { Literal::Null }
+            / table:column_name() "." column:column_name() { Literal::Ident(format!("{}.{}", table, column)) }
+            / id:column_name() { Literal::Ident(id) }
             / s:string_literal() { Literal::StringLiteral(s) }
-            / n:number_literal() { Literal::NumberLiteral(n) }
+            / n:number_literal() { n }
 
         // ---- Identifiers and keywords ----
 
@@ -55,43 +343,212 @@ peg::parser! {
             }
             / expected!("identifier")
 
-        // Strings (surrounded by single quotes)
-        rule string_literal() -> String
-            = "'" s:$([^ '\'']*) "'" { s.to_string() }
+        /// Table or column name, either bare or quoted as "name", [name], `name` or 'name'
+        rule name() -> String
+            = ident()
+            / quoted_name()
+            / string_literal()
 
-        // Numbers
-        rule number_literal() -> f64
-            = n:$(['0'..='9']+ ("." ['0'..='9']+)? ) { n.parse().unwrap() }
+        /// A name in an expression, where a single quoted string is a value instead
+        rule column_name() -> String
+            = ident()
+            / quoted_name()
 
-        /// Case-insensitive SELECT
-        rule kw_select()
-            = quiet!{ 
-                "SELECT" / "select" / "Select" // simple way; later you can make a helper
-            }
-            / expected!("SELECT")
+        rule quoted_name() -> String
+            = "\"" s:$(([^ '"'] / "\"\"")*) "\"" { s.replace("\"\"", "\"") }
+            / "[" s:$([^ ']']*) "]" { s.to_string() }
+            / "`" s:$(([^ '`'] / "``")*) "`" { s.replace("``", "`") }
 
-        /// Case-insensitive FROM
-        rule kw_from()
-            = quiet!{
-                "FROM" / "from" / "From"
-            }
-            / expected!("FROM")
+        // Strings (surrounded by single quotes, '' escapes a quote)
+        rule string_literal() -> String
+            = "'" s:$(([^ '\''] / "''")*) "'" { s.replace("''", "'") }
 
-        /// Case-insensitive COUNT
-        rule kw_count()
-            = quiet!{
-                "COUNT" / "count" / "Count"
+        // Blobs (X'..' with an even number of hex digits)
+        rule blob_literal() -> Vec<u8>
+            = ['x' | 'X'] "'" h:$((['0'..='9' | 'a'..='f' | 'A'..='F']*<2>)*) "'" {
+                h.as_bytes()
+                    .chunks(2)
+                    .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+                    .collect()
             }
-            / expected!("COUNT")
 
-        rule kw_where()
-            = quiet!{
-                "WHERE" / "where" / "Where"
+        // Numbers: integers stay exact unless they overflow i64, anything with a fraction or exponent is real
+        rule number_literal() -> Literal
+            = n:$((['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+) (['e' | 'E'] ['+' | '-']? ['0'..='9']+)?) {
+                match n.parse::<i64>() {
+                    Ok(i) => Literal::IntegerLiteral(i),
+                    Err(_) => Literal::RealLiteral(n.parse().unwrap()),
+                }
             }
-            / expected!("WHERE")
+
+        /// Case-insensitive keyword, matched as a whole word
+        rule kw(keyword: &'static str)
+            = quiet!{ w:$(['a'..='z' | 'A'..='Z' | '_']+) !['0'..='9'] {? if w.eq_ignore_ascii_case(keyword) { Ok(()) } else { Err(keyword) } } }
+            / expected!(keyword)
 
         // ---- Whitespace & comments ----
         rule _()
-            = quiet!{ [' ' | '\t' | '\n' | '\r']* }
+            = quiet!{ ([' ' | '\t' | '\n' | '\r'] / "--" [^ '\n']* / "/*" (!"*/" [_])* "*/")* }
+    }
+}
+
+fn compare(op: CompareOp, left: Expr, right: Expr) -> Expr {
+    Expr::Compare { op, left: Box::new(left), right: Box::new(right) }
+}
+
+fn is_null(expr: Expr, negated: bool) -> Expr {
+    Expr::Is { left: Box::new(expr), right: Box::new(Expr::Literal(Literal::Null)), negated }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+}
+
+/// `-expr`, folded into the literal when negating a number so that `-5` stays a constant.
+fn negate(expr: Expr) -> Expr {
+    match expr {
+        Expr::Literal(number @ (Literal::IntegerLiteral(_) | Literal::RealLiteral(_))) => Expr::Literal(negate_number(number)),
+        expr => Expr::Unary { op: UnaryOp::Negate, expr: Box::new(expr) },
+    }
+}
+
+fn negate_number(number: Literal) -> Literal {
+    match number {
+        Literal::IntegerLiteral(i) => match i.checked_neg() {
+            Some(i) => Literal::IntegerLiteral(i),
+            None => Literal::RealLiteral(-(i as f64)),
+        },
+        // 9223372036854775808 only fits as a real, but its negation is the smallest integer
+        Literal::RealLiteral(9223372036854775808.0) => Literal::IntegerLiteral(i64::MIN),
+        Literal::RealLiteral(r) => Literal::RealLiteral(-r),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_in_any_case() {
+        for sql in ["SELECT a FROM t WHERE a = 1", "select a from t where a = 1", "sElEcT a FrOm t wHeRe a = 1"] {
+            let select = sql_parser::statement(sql).unwrap();
+            assert_eq!(select.table, "t");
+            assert!(select.where_expr.is_some());
+        }
+        assert!(sql_parser::statement("selectx a from t").is_err());
+    }
+
+    #[test]
+    fn quoted_table_and_column_names() {
+        let select = sql_parser::statement("select \"order\", [x y], `a``b` from \"group\" where [x y] > 1").unwrap();
+        assert_eq!(select.table, "group");
+        let columns = select.columns.iter().map(|column| match column {
+            SelectItem::Expr { expr: Expr::Literal(Literal::Ident(name)), .. } => name.as_str(),
+            other => panic!("unexpected column {:?}", other),
+        }).collect::<Vec<_>>();
+        assert_eq!(columns, ["order", "x y", "a`b"]);
+        assert_eq!(sql_parser::statement("select a from 'my table'").unwrap().table, "my table");
+    }
+
+    #[test]
+    fn joins_and_table_aliases() {
+        let select = sql_parser::statement("select t.a, u.b from t as x join u on x.id = u.id inner join v w cross join z, y where a").unwrap();
+        assert_eq!((select.table.as_str(), select.table_alias.as_deref()), ("t", Some("x")));
+        let joins = select.joins.iter().map(|join| (join.table.as_str(), join.alias.as_deref(), join.constraint.is_some())).collect::<Vec<_>>();
+        assert_eq!(joins, [("u", None, true), ("v", Some("w"), false), ("z", None, false), ("y", None, false)]);
+        assert!(matches!(&select.columns[0], SelectItem::Expr { expr: Expr::Literal(Literal::Ident(name)), .. } if name == "t.a"));
+        assert!(select.where_expr.is_some());
+        assert!(sql_parser::statement("select a from t where a").unwrap().table_alias.is_none());
+    }
+
+    #[test]
+    fn blob_literals() {
+        for (sql, bytes) in [("x'00fF10'", vec![0x00, 0xff, 0x10]), ("X''", vec![])] {
+            assert!(matches!(sql_parser::expression(sql), Ok(Expr::Literal(Literal::BlobLiteral(b))) if b == bytes), "{}", sql);
+        }
+        assert!(sql_parser::expression("x'0'").is_err());
+        assert!(sql_parser::expression("x'zz'").is_err());
+    }
+
+    #[test]
+    fn integer_and_real_literals() {
+        let literal = |sql| match sql_parser::expression(sql) {
+            Ok(Expr::Literal(literal)) => format!("{:?}", literal),
+            other => panic!("{} parsed to {:?}", sql, other),
+        };
+        assert_eq!(literal("9007199254740993"), "IntegerLiteral(9007199254740993)");
+        assert_eq!(literal("9223372036854775807"), "IntegerLiteral(9223372036854775807)");
+        assert_eq!(literal("9223372036854775808"), "RealLiteral(9.223372036854776e18)");
+        assert_eq!(literal("1.0"), "RealLiteral(1.0)");
+        assert_eq!(literal(".5"), "RealLiteral(0.5)");
+        assert_eq!(literal("1e3"), "RealLiteral(1000.0)");
+    }
+
+    #[test]
+    fn create_table_definitions() {
+        let sql = "CREATE TABLE \"order items\" (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            price DECIMAL(10, 2) NOT NULL DEFAULT 0,
+            note, -- no type
+            [group] VARCHAR(20) COLLATE NOCASE UNIQUE,
+            parent UNSIGNED BIG INT REFERENCES other(id) ON DELETE CASCADE,
+            total AS (price * 2),
+            CONSTRAINT positive CHECK (price >= 0 AND note <> 'x,y'),
+            UNIQUE (price, note),
+            FOREIGN KEY (parent, note) REFERENCES other(a, b)
+        )";
+        let table = sql_parser::create_table(sql).unwrap();
+        assert_eq!(table.name, "order items");
+        let columns = table.columns.iter().map(|column| (column.name.as_str(), column.type_name.as_deref())).collect::<Vec<_>>();
+        assert_eq!(columns, [
+            ("id", Some("INTEGER")),
+            ("price", Some("DECIMAL(10, 2)")),
+            ("note", None),
+            ("group", Some("VARCHAR(20)")),
+            ("parent", Some("UNSIGNED BIG INT")),
+            ("total", None),
+        ]);
+        assert_eq!(table.columns[0].constraints, [ColumnConstraint::PrimaryKey { order: None, autoincrement: true }]);
+        assert_eq!(table.columns[1].constraints, [ColumnConstraint::NotNull, ColumnConstraint::Default(DefaultValue::Literal(Literal::IntegerLiteral(0)))]);
+        assert_eq!(table.columns[3].constraints, [ColumnConstraint::Collate("NOCASE".into()), ColumnConstraint::Unique]);
+        assert_eq!(table.columns[4].constraints, [ColumnConstraint::References(ForeignKey { table: "other".into(), columns: vec!["id".into()] })]);
+        assert_eq!(table.columns[5].constraints, [ColumnConstraint::Generated { expr: "price * 2".into(), stored: false }]);
+        assert_eq!(table.constraints, [
+            TableConstraint::Check("price >= 0 AND note <> 'x,y'".into()),
+            TableConstraint::Unique(vec!["price".into(), "note".into()]),
+            TableConstraint::ForeignKey {
+                columns: vec!["parent".into(), "note".into()],
+                references: ForeignKey { table: "other".into(), columns: vec!["a".into(), "b".into()] },
+            },
+        ]);
+        assert!(!table.without_rowid);
+    }
+
+    #[test]
+    fn create_table_options_and_errors() {
+        let table = sql_parser::create_table("create table if not exists main.t(a text primary key desc, b) without rowid").unwrap();
+        assert_eq!(table.name, "t");
+        assert!(table.without_rowid);
+        assert_eq!(table.columns[0].constraints, [ColumnConstraint::PrimaryKey { order: Some(SortOrder::Desc), autoincrement: false }]);
+        assert_eq!(sql_parser::create_table("CREATE TABLE t(a, PRIMARY KEY (a))").unwrap().constraints, [TableConstraint::PrimaryKey(vec!["a".into()])]);
+        assert!(sql_parser::create_table("CREATE TABLE t(a,").is_err());
+        assert!(sql_parser::create_table("CREATE TABLE t()").is_err());
+    }
+
+    #[test]
+    fn create_index_definitions() {
+        let sql = "CREATE UNIQUE INDEX IF NOT EXISTS \"by name\" ON people (last DESC, first COLLATE NOCASE, lower(email) ASC) WHERE age > 18";
+        let index = sql_parser::create_index(sql).unwrap();
+        assert_eq!((index.name.as_str(), index.table.as_str(), index.unique), ("by name", "people", true));
+        assert_eq!(index.columns, [
+            IndexedColumn { key: IndexKey::Column("last".into()), collation: None, order: SortOrder::Desc },
+            IndexedColumn { key: IndexKey::Column("first".into()), collation: Some("NOCASE".into()), order: SortOrder::Asc },
+            IndexedColumn { key: IndexKey::Expression("lower(email)".into()), collation: None, order: SortOrder::Asc },
+        ]);
+        assert_eq!(index.where_expr, sql_parser::expression("age > 18").ok());
+        let index = sql_parser::create_index("create index i on t(a)").unwrap();
+        assert!(!index.unique && index.where_expr.is_none());
+        assert!(sql_parser::create_index("CREATE INDEX i ON t()").is_err());
     }
-}
\ No newline at end of file
+}
@@ -1,23 +1,248 @@
+use anyhow::{Result, bail};
+
+use crate::modules::{
+    ast::{ColumnConstraint, CreateTableStatement, Expr, Literal, SortOrder, TableConstraint, format_real},
+    eval::Scope,
+    header::TextEncoding,
+    sql_parser::sql_parser,
+};
+
+/// Names that refer to the rowid unless a real column shadows them.
+pub const ROWID_NAMES: [&str; 3] = ["rowid", "oid", "_rowid_"];
 
 pub struct Table {
     pub name: String,
     pub rootpage: u32,
-    pub columns: Vec<Column>
+    pub columns: Vec<Column>,
+    /// Index of the INTEGER PRIMARY KEY column, which is stored as the rowid instead of in the record
+    pub rowid_alias: Option<usize>,
+    pub without_rowid: bool,
+    /// VIRTUAL columns in the order they are computed, each after the VIRTUAL columns it reads
+    pub generated_order: Vec<usize>,
 }
 
 impl Table {
     pub fn new(name: &str, rootpage: u32, columns: Vec<Column>) -> Self {
-        Self { name: name.into(), rootpage, columns }
+        let mut primary_keys = columns.iter().enumerate().filter(|(_, c)| c.primary_key);
+        let rowid_alias = match (primary_keys.next(), primary_keys.next()) {
+            (Some((i, column)), None) if column.ctype.eq_ignore_ascii_case("INTEGER") => Some(i),
+            _ => None,
+        };
+        Self { name: name.into(), rootpage, columns, rowid_alias, without_rowid: false, generated_order: vec![] }
+    }
+
+    pub fn from_definition(definition: CreateTableStatement, rootpage: u32) -> Self {
+        let table_primary_key = definition.constraints.iter().find_map(|constraint| match constraint {
+            TableConstraint::PrimaryKey(columns) => Some(columns.clone()),
+            _ => None,
+        }).unwrap_or_default();
+        let columns = definition.columns.into_iter().map(|column_definition| {
+            let mut column = Column::new(&column_definition.name, &column_definition.type_name.unwrap_or_default());
+            // "INTEGER PRIMARY KEY DESC" is not a rowid alias, see https://www.sqlite.org/lang_createtable.html#rowid
+            column.primary_key = column_definition.constraints.iter().any(|constraint| {
+                matches!(constraint, ColumnConstraint::PrimaryKey { order, .. } if *order != Some(SortOrder::Desc))
+            }) || table_primary_key.iter().any(|name| name.eq_ignore_ascii_case(&column.name));
+            if let Some(expr) = column_definition.constraints.iter().find_map(|constraint| match constraint {
+                ColumnConstraint::Generated { expr, stored: false } => Some(expr),
+                _ => None,
+            }) {
+                column.is_virtual = true;
+                column.generated = sql_parser::expression(expr).ok();
+            }
+            column.collation = column_definition.constraints.iter().find_map(|constraint| match constraint {
+                ColumnConstraint::Collate(collation) => Some(collation.clone()),
+                _ => None,
+            });
+            column
+        }).collect();
+        let mut table = Self::new(&definition.name, rootpage, columns);
+        table.order_generated();
+        if definition.without_rowid {
+            table.rowid_alias = None;
+            table.without_rowid = true;
+        }
+        table
+    }
+
+    /// Orders the VIRTUAL columns so that each comes after those its expression reads. Columns
+    /// left in a cycle, or reading one we cannot compute, lose their expression.
+    fn order_generated(&mut self) {
+        let reads = |expr: &Expr, j: usize| reads_column(expr, &self.columns[j].name);
+        let mut pending = (0..self.columns.len()).filter(|&j| self.columns[j].is_virtual).collect::<Vec<_>>();
+        let mut order = vec![];
+        while let Some(i) = pending.iter().position(|&j| {
+            self.columns[j].generated.as_ref().is_some_and(|expr| pending.iter().all(|&k| !reads(expr, k)))
+        }) {
+            order.push(pending.remove(i));
+        }
+        for j in pending {
+            self.columns[j].generated = None;
+        }
+        self.generated_order = order;
+    }
+
+    /// Maps a record of this table to one value per column, followed by the hidden rowid column.
+    /// VIRTUAL generated columns are computed from the stored values, with text in `encoding`.
+    pub fn row_from_record(&self, rowid: i64, record: Vec<Literal>, encoding: TextEncoding) -> Result<Vec<Literal>> {
+        let mut record = record.into_iter();
+        // columns added by ALTER TABLE are missing from older records
+        let mut cols = self.columns.iter()
+            .map(|column| if column.is_virtual { Literal::Null } else { record.next().unwrap_or(Literal::Null) })
+            .collect::<Vec<_>>();
+        for (j, column) in self.columns.iter().enumerate() {
+            if self.rowid_alias == Some(j) {
+                cols[j] = Literal::IntegerLiteral(rowid);
+            } else if let Literal::IntegerLiteral(n) = cols[j] {
+                // reals with no fractional part are stored as integers on disk
+                if column.affinity() == Affinity::Real {
+                    cols[j] = Literal::RealLiteral(n as f64);
+                }
+            }
+        }
+        cols.push(Literal::IntegerLiteral(rowid)); // hidden rowid column
+        if let Some(column) = self.columns.iter().find(|column| column.is_virtual && column.generated.is_none()) {
+            bail!("unsupported generated column: {}", column.name);
+        }
+        if !self.generated_order.is_empty() {
+            let scope = Scope::of_table(self, encoding);
+            for &j in &self.generated_order {
+                let column = &self.columns[j];
+                if let Some(expr) = &column.generated {
+                    cols[j] = column.affinity().apply(scope.eval(expr, &cols)?);
+                }
+            }
+        }
+        Ok(cols)
+    }
+
+    /// Position of a column in a decoded row. The hidden rowid column comes right after the declared ones.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        if let Some(i) = self.columns.iter().position(|c| c.name.eq_ignore_ascii_case(name)) {
+            return Some(i);
+        }
+        if ROWID_NAMES.iter().any(|rowid| rowid.eq_ignore_ascii_case(name)) {
+            return Some(self.columns.len());
+        }
+        None
+    }
+}
+
+/// Whether `expr` refers to the column called `name`.
+fn reads_column(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Literal(Literal::Ident(column)) => column.eq_ignore_ascii_case(name),
+        expr => expr.children().into_iter().any(|child| reads_column(child, name)),
+    }
+}
+
+/// Column affinity, derived from the declared type with the rules of https://www.sqlite.org/datatype3.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    /// Converts `value` the way SQLite does before storing it in a column, or comparing it with one,
+    /// of this affinity. Values that cannot be converted losslessly are returned unchanged.
+    pub fn apply(self, value: Literal) -> Literal {
+        match (self, value) {
+            (Self::Text, Literal::IntegerLiteral(n)) => Literal::StringLiteral(n.to_string()),
+            (Self::Text, Literal::RealLiteral(n)) => Literal::StringLiteral(format_real(n)),
+            (Self::Integer | Self::Numeric | Self::Real, Literal::StringLiteral(text)) => {
+                let trimmed = text.trim();
+                let number = match trimmed.parse::<i64>() {
+                    Ok(n) => Literal::IntegerLiteral(n),
+                    Err(_) => match trimmed.parse::<f64>() {
+                        // a trailing or leading '.' is fine, but "inf" and "nan" are not numbers in SQL
+                        Ok(n) if n.is_finite() && trimmed.chars().any(|c| c.is_ascii_digit()) && !trimmed.contains(['i', 'I', 'n', 'N']) => Literal::RealLiteral(n),
+                        _ => return Literal::StringLiteral(text),
+                    },
+                };
+                self.apply(number)
+            },
+            (Self::Integer | Self::Numeric, Literal::RealLiteral(n)) if n.fract() == 0.0 && n.abs() < 9.2e18 => Literal::IntegerLiteral(n as i64),
+            (Self::Real, Literal::IntegerLiteral(n)) => Literal::RealLiteral(n as f64),
+            (_, value) => value,
+        }
     }
 }
 
 pub struct Column {
     pub name: String,
-    pub _ctype: String,
+    pub ctype: String,
+    pub primary_key: bool,
+    /// VIRTUAL generated columns are computed on read and take no space in the record
+    pub is_virtual: bool,
+    /// Expression of a VIRTUAL column, `None` when we cannot parse or order it
+    pub generated: Option<Expr>,
+    pub collation: Option<String>,
 }
 
 impl Column {
     pub fn new(name: &str, ctype: &str) -> Self {
-        Self { name: name.into(), _ctype: ctype.into() }
+        Self { name: name.into(), ctype: ctype.into(), primary_key: false, is_virtual: false, generated: None, collation: None }
+    }
+
+    pub fn affinity(&self) -> Affinity {
+        let ctype = self.ctype.to_uppercase();
+        if ctype.contains("INT") {
+            Affinity::Integer
+        } else if ctype.contains("CHAR") || ctype.contains("CLOB") || ctype.contains("TEXT") {
+            Affinity::Text
+        } else if ctype.contains("BLOB") || ctype.is_empty() {
+            Affinity::Blob
+        } else if ctype.contains("REAL") || ctype.contains("FLOA") || ctype.contains("DOUB") {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::testing;
+
+    fn table(sql: &str) -> Table {
+        Table::from_definition(sql_parser::create_table(sql).unwrap(), 2)
+    }
+
+    #[test]
+    fn finds_the_rowid_alias() {
+        for (sql, alias) in [
+            ("CREATE TABLE t(id INTEGER PRIMARY KEY, name TEXT)", Some(0)),
+            ("CREATE TABLE t(id TEXT, user_id integer primary key asc)", Some(1)),
+            ("CREATE TABLE t(id INTEGER, v, PRIMARY KEY(id))", Some(0)),
+            ("CREATE TABLE t(id INT PRIMARY KEY)", None),
+            ("CREATE TABLE t(id INTEGER PRIMARY KEY DESC)", None),
+            ("CREATE TABLE t(a INTEGER, b INTEGER, PRIMARY KEY(a, b))", None),
+            ("CREATE TABLE t(id INTEGER PRIMARY KEY, v) WITHOUT ROWID", None),
+            ("CREATE TABLE t(id, v)", None),
+        ] {
+            assert_eq!(table(sql).rowid_alias, alias, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn rowid_names_unless_a_column_has_them() {
+        let t = table("CREATE TABLE t(a, oid)");
+        assert_eq!(t.column_index("ROWID"), Some(2));
+        assert_eq!(t.column_index("_rowid_"), Some(2));
+        assert_eq!(t.column_index("oid"), Some(1));
+        assert_eq!(t.column_index("b"), None);
+    }
+
+    #[test]
+    fn reads_the_rowid_alias_and_pseudo_columns() {
+        let connection = testing::open("types");
+        assert_eq!(testing::query(&connection, "SELECT user_id, id, rowid, _rowid_ FROM keyed"), ["5|third|5|5", "10|first|10|10", "20|second|20|20"]);
+        assert_eq!(testing::query(&connection, "SELECT * FROM keyed WHERE user_id = 10"), ["first|10"]);
+        assert_eq!(testing::query(&connection, "SELECT id FROM keyed WHERE id = 'third'"), ["third"]);
+        assert_eq!(testing::query(&connection, "SELECT oid, rowid, v FROM shadow"), ["a|1|1", "b|2|2"]);
     }
 }
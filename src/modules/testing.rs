@@ -0,0 +1,19 @@
+//! Databases made by the sqlite3 shell for the unit tests, see `testdata/build.sh`.
+
+use std::path::PathBuf;
+
+use crate::modules::connection::Connection;
+
+/// Opens `testdata/<name>.db`.
+pub fn open(name: &str) -> Connection {
+    let path = [env!("CARGO_MANIFEST_DIR"), "testdata", &format!("{}.db", name)].iter().collect::<PathBuf>();
+    Connection::open(path).unwrap()
+}
+
+/// Result rows of `sql`, each printed the way the CLI prints it.
+pub fn query(connection: &Connection, sql: &str) -> Vec<String> {
+    let mut statement = connection.prepare(sql).unwrap();
+    statement.query().unwrap().map(|row| {
+        row.unwrap().values().iter().map(|value| value.to_string()).collect::<Vec<_>>().join("|")
+    }).collect()
+}
@@ -0,0 +1,8 @@
+-- A table whose CREATE TABLE statement is cut short, next to one that is fine.
+
+create table good(a);
+insert into good values (1);
+create table broken(a);
+.dbconfig defensive off
+pragma writable_schema = on;
+update sqlite_schema set sql = 'CREATE TABLE broken(a,' where name = 'broken';
@@ -0,0 +1,12 @@
+#!/bin/sh
+#
+# Rebuilds the test databases from the SQL scripts next to this file with the sqlite3 shell.
+# The .db files are committed, so running the tests does not need sqlite3.
+
+set -e # Exit early if any commands fail
+
+cd "$(dirname "$0")"
+for script in *.sql; do
+  rm -f "${script%.sql}.db"
+  sqlite3 "${script%.sql}.db" ".read $script" > /dev/null
+done
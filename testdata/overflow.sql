@@ -0,0 +1,14 @@
+-- Small pages with 8 reserved bytes, so long text spills to overflow pages in both the table and the index.
+.filectrl reserve_bytes 8
+pragma page_size = 512;
+
+create table docs(id integer primary key, body text);
+create index docs_body on docs(body);
+
+-- the body of row n is '0001,0002,...,n'
+with recursive numbers(n, body) as (
+  select 1, '0001'
+  union all
+  select n + 1, body || printf(',%04d', n + 1) from numbers where n < 2000
+)
+insert into docs select n, body from numbers where n in (1, 20, 100, 101, 500, 2000);
@@ -0,0 +1,18 @@
+-- Tables spread over many small pages, with indexes, for the query tests.
+pragma page_size = 512;
+
+-- a is i % 7, b cycles through mixed case words, c is NULL for every fifth row, d mixes types
+create table items(id integer primary key, a int, b text collate nocase, c real, d);
+with recursive n(i) as (select 1 union all select i + 1 from n where i < 300)
+insert into items
+select i, i % 7,
+  case i % 4 when 0 then 'apple' when 1 then 'Banana' when 2 then 'cherry' else 'APPLE' end,
+  case when i % 5 = 0 then null else i * 0.5 end,
+  case i % 3 when 0 then i when 1 then 'x' || i else null end
+from n;
+create index items_a on items(a);
+create index items_b on items(b);
+create index items_c_desc on items(c desc);
+
+create table owners(id integer primary key, item_id int, name text);
+insert into owners values (1, 3, 'ann'), (2, 3, 'bob'), (3, 10, 'cid'), (4, 999, 'dan'), (5, null, 'eve');
@@ -0,0 +1,39 @@
+-- Enough objects on small pages that sqlite_schema has an interior root page.
+pragma page_size = 512;
+
+create table t01(id integer primary key, name text);
+create table t02(id integer primary key, name text);
+create table t03(id integer primary key, name text);
+create table t04(id integer primary key, name text);
+create table t05(id integer primary key, name text);
+create table t06(id integer primary key, name text);
+create table t07(id integer primary key, name text);
+create table t08(id integer primary key, name text);
+create table t09(id integer primary key, name text);
+create table t10(id integer primary key, name text);
+create table t11(id integer primary key, name text);
+create table t12(id integer primary key, name text);
+create table t13(id integer primary key, name text);
+create table t14(id integer primary key, name text);
+create table t15(id integer primary key, name text);
+create table t16(id integer primary key, name text);
+create table t17(id integer primary key, name text);
+create table t18(id integer primary key, name text);
+create table t19(id integer primary key, name text);
+create table t20(id integer primary key, name text);
+create table t21(id integer primary key, name text);
+create table t22(id integer primary key, name text);
+create table t23(id integer primary key, name text);
+create table t24(id integer primary key, name text);
+create table t25(id integer primary key, name text);
+create table t26(id integer primary key, name text);
+create table t27(id integer primary key, name text);
+create table t28(id integer primary key, name text);
+create table t29(id integer primary key, name text);
+create table t30(id integer primary key, name text unique);
+create index t01_name on t01(name);
+create index t10_name on t10(name);
+create index t20_name on t20(name);
+create view v30 as select name from t30;
+create trigger t30_insert after insert on t30 begin insert into t01(name) values (new.name); end;
+insert into t30(name) values ('last'), ('table');
@@ -0,0 +1,17 @@
+-- Values of every storage class and tables with unusual names and keys.
+
+create table "group"("order" integer, [x y] text, `a``b` int);
+insert into "group" values (1, 'one', 10), (2, 'two', 20), (3, 'three', 30);
+
+-- one value of each storage class and integer size
+create table vals(id integer primary key, v);
+insert into vals values
+  (1, null), (2, 0), (3, 1), (4, 100), (5, -1000), (6, 8388607), (7, -8388608), (8, 2147483648),
+  (9, 140737488355327), (10, -140737488355328), (11, 9007199254740993), (12, 9223372036854775807),
+  (13, -9223372036854775808), (14, 2.5), (15, -0.1), (16, 1e100), (17, 'text'), (18, ''), (19, x''), (20, x'00ff10');
+
+-- the rowid alias is user_id, id is an ordinary column, and oid hides the rowid of shadow
+create table keyed(id text, user_id integer primary key);
+insert into keyed values ('first', 10), ('second', 20), ('third', 5);
+create table shadow(oid text, v);
+insert into shadow values ('a', 1), ('b', 2);
@@ -0,0 +1,8 @@
+-- A UTF-16be database with non-ASCII text, including a character outside the BMP that sorts
+-- differently in UTF-16 than in code point order.
+pragma encoding = 'UTF-16be';
+
+create table words(id integer primary key, word text);
+create index words_word on words(word);
+
+insert into words values (1, 'apple'), (2, 'Äpfel'), (3, '日本'), (4, '😀'), (5, '｡'), (6, 'zebra');